@@ -25,16 +25,34 @@ cache = {
 }
 
 
+def requested_fields():
+    # comma separated field names, each optionally followed by its unit after a colon
+    fields = request.args.get('fields')
+    if fields is None:
+        return None
+    return {field.split(':')[0] for field in fields.split(',')}
+
+
+def only_requested(result):
+    fields = requested_fields()
+    if fields is None:
+        return result
+    return {
+        'numeric_fields': {k: v for k, v in result['numeric_fields'].items() if k in fields},
+        'string_fields': {k: v for k, v in result['string_fields'].items() if k in fields},
+    }
+
+
 @app.route('/search', methods=['GET'])
 def search():
     query = request.args.get('query')
     if query in cache.keys():
-        return json.dumps(cache[query])
+        return json.dumps(only_requested(cache[query]))
     product = openfoodfacts.products.search(query, page_size=2)['products'][0]
     # print(product)
     nutriments = product['nutriments']
     print(nutriments)
-    return json.dumps({
+    return json.dumps(only_requested({
         'numeric_fields': {
             'energy_density': {
                 'Normal': {
@@ -50,7 +68,7 @@ def search():
             },
         },
         'string_fields': {}
-    })
+    }))
 
 
 if __name__ == '__main__':
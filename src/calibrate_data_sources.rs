@@ -0,0 +1,280 @@
+pub mod datasource;
+pub mod datasources;
+pub mod entity_resolution;
+pub mod labelled_data;
+pub mod numeric;
+pub mod search_engine;
+pub mod search_engine_config;
+pub mod search_result;
+pub mod string;
+use std::{collections::HashMap, io::prelude::*, path::PathBuf};
+
+use clap::Parser;
+use futures::{stream, StreamExt};
+use labelled_data::LabelledData;
+use numeric::NumericFieldValue;
+use search_engine_config::{Config, DataSourceConfig, NumericCalibration};
+use serde::Serialize;
+use string::{ConfusionMatrix, StringFieldValue};
+
+/// Estimates the error model of every configured data source from labelled ground truth
+/// and writes a calibrated `data_sources` section for the config file
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Path to the config file containing the fields and data sources to calibrate. Defaults to `config.yaml`
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Path to a file containing the labelled samples
+    #[arg(short, long)]
+    data: PathBuf,
+    /// File to write the calibrated data sources to. Defaults to stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Number of queries sent to a data source concurrently
+    #[arg(long, default_value_t = 16)]
+    concurrency: usize,
+}
+
+/// Reports deviating by more than this many (inflated) standard deviations count as outliers
+const OUTLIER_THRESHOLD: f64 = 4.0;
+
+/// Ratio between the median absolute deviation and the standard deviation of a normal distribution
+const MAD_TO_SIGMA: f64 = 0.6745;
+
+#[derive(Debug, Clone, PartialEq)]
+struct NumericErrorModel {
+    bias: f64,
+    sigma_inflation: f64,
+    /// Share of the reports with a spread that deviate by more than `OUTLIER_THRESHOLD`
+    outlier_rate: f64,
+    /// Mean squared deviation of the calibrated reports in standard deviations, clipped at `OUTLIER_THRESHOLD`.
+    /// About 1 for normally distributed errors, more if outliers fatten the tails
+    mean_squared_z: f64,
+    /// Number of reports with a spread. Exact reports are left out, as their deviations have no scale
+    spread_samples: usize,
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[middle - 1] + values[middle]) / 2.0)
+    } else {
+        Some(values[middle])
+    }
+}
+
+/// Robustly estimates bias, noise scale and outlier rate from pairs of
+/// (reported mean - true value, reported standard deviation)
+fn estimate_numeric_error_model(observations: &[(f64, f64)]) -> Option<NumericErrorModel> {
+    let bias = median(observations.iter().map(|(error, _)| *error).collect())?;
+    let z_scores: Vec<f64> = observations
+        .iter()
+        .filter(|(_, sigma)| *sigma > 0.0)
+        .map(|(error, sigma)| ((error - bias) / sigma).abs())
+        .collect();
+    let sigma_inflation = median(z_scores.clone())
+        .map(|mad| mad / MAD_TO_SIGMA)
+        .filter(|s| s.is_finite() && *s > 0.0)
+        .unwrap_or(1.0);
+    let calibrated: Vec<f64> = z_scores.iter().map(|z| z / sigma_inflation).collect();
+    let outliers = calibrated.iter().filter(|z| **z > OUTLIER_THRESHOLD).count();
+    let spread_samples = calibrated.len();
+    let (outlier_rate, mean_squared_z) = match spread_samples {
+        0 => (0.0, 1.0),
+        n => (
+            outliers as f64 / n as f64,
+            calibrated.iter().map(|z| z.min(OUTLIER_THRESHOLD).powi(2)).sum::<f64>() / n as f64,
+        ),
+    };
+    Some(NumericErrorModel {
+        bias,
+        sigma_inflation,
+        outlier_rate,
+        mean_squared_z,
+        spread_samples,
+    })
+}
+
+/// Weight maximizing the likelihood of the true values under the calibrated reports raised to its power.
+/// Tempering a normal report by `w` divides its sigma by `sqrt(w)`, so the optimum is the inverse of the
+/// mean squared z-score. It is capped at 1, as the calibration already fits the spread of the inliers
+fn likelihood_weight(models: &[NumericErrorModel]) -> Option<f64> {
+    let samples: usize = models.iter().map(|m| m.spread_samples).sum();
+    let squared_z: f64 = models
+        .iter()
+        .map(|m| m.mean_squared_z * m.spread_samples as f64)
+        .sum();
+    (samples > 0 && squared_z > 0.0).then(|| (samples as f64 / squared_z).min(1.0))
+}
+
+#[derive(Debug, Default)]
+struct SourceObservations {
+    /// Pairs of (reported mean - true value, reported standard deviation) per numeric field
+    numeric: HashMap<String, Vec<(f64, f64)>>,
+    /// Accumulated probability of each reported value given the true value, per string field
+    string: HashMap<String, HashMap<String, HashMap<String, f64>>>,
+}
+
+async fn observe(
+    data_source: &search_engine::ConfiguredDataSource,
+    data: &LabelledData,
+    concurrency: usize,
+) -> SourceObservations {
+    let results: Vec<_> = stream::iter(data.samples.iter())
+        .map(|sample| async move {
+            (
+                sample,
+                data_source
+                    .data_source
+                    .search(&sample.query.clone().into())
+                    .await,
+            )
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+    let mut observations = SourceObservations::default();
+    for (sample, result) in results {
+        let result = match result {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        for (key, truth) in &sample.numeric_fields {
+            match result.numeric_fields.get(key) {
+                None | Some(NumericFieldValue::Error) => {}
+                Some(value) => observations
+                    .numeric
+                    .entry(key.clone())
+                    .or_default()
+                    .push((value.mu() - truth, value.sigma())),
+            }
+        }
+        for (key, truth) in &sample.string_fields {
+            let reported = match result.string_fields.get(key) {
+                Some(StringFieldValue::Exact(e)) => HashMap::from([(e.clone(), 1.0)]),
+                Some(StringFieldValue::Distribution(d)) => d.clone(),
+                None | Some(StringFieldValue::Error) => continue,
+            };
+            let row = observations
+                .string
+                .entry(key.clone())
+                .or_default()
+                .entry(truth.clone())
+                .or_default();
+            for (value, probability) in reported {
+                *row.entry(value).or_default() += probability;
+            }
+        }
+    }
+    observations
+}
+
+fn calibrate(config: &mut DataSourceConfig, observations: SourceObservations) {
+    let mut models = vec![];
+    for (key, errors) in observations.numeric {
+        if let Some(model) = estimate_numeric_error_model(&errors) {
+            eprintln!(
+                "  {}: bias {}, noise scale {}, outlier rate {} ({} samples)",
+                key,
+                model.bias,
+                model.sigma_inflation,
+                model.outlier_rate,
+                errors.len()
+            );
+            config.calibration.insert(
+                key,
+                NumericCalibration {
+                    bias: model.bias,
+                    sigma_inflation: model.sigma_inflation,
+                },
+            );
+            models.push(model);
+        }
+    }
+    for (key, rows) in observations.string {
+        eprintln!("  {}: confusion matrix over {} true values", key, rows.len());
+        let matrix = rows
+            .into_iter()
+            .map(|(truth, row)| {
+                let total: f64 = row.values().sum();
+                let row = row.into_iter().map(|(k, v)| (k, v / total)).collect();
+                (truth, row)
+            })
+            .collect();
+        config
+            .confusion_matrices
+            .insert(key, ConfusionMatrix(matrix));
+    }
+    if let Some(weight) = likelihood_weight(&models) {
+        eprintln!("  weight {}", weight);
+        config.weight = weight;
+    }
+}
+
+#[derive(Serialize)]
+struct CalibratedDataSources {
+    data_sources: Vec<DataSourceConfig>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Cli::parse();
+    let config_path = args.config.unwrap_or_else(|| PathBuf::from("config.yaml"));
+    let configuration = Config::load(config_path).unwrap();
+    let data = LabelledData::load_from_file(args.data).unwrap();
+    let mut calibrated = Vec::with_capacity(configuration.data_sources.len());
+    for mut data_source_config in configuration.data_sources {
+        eprintln!(
+            "Data source {}: {}",
+            data_source_config.name, data_source_config.source
+        );
+        let data_source = data_source_config.clone().to_configured_data_source().await;
+        let observations = observe(&data_source, &data, args.concurrency.max(1)).await;
+        calibrate(&mut data_source_config, observations);
+        calibrated.push(data_source_config);
+    }
+    let snippet = serde_yaml::to_string(&CalibratedDataSources {
+        data_sources: calibrated,
+    })
+    .unwrap();
+    match args.output {
+        Some(path) => std::fs::File::create(path)
+            .unwrap()
+            .write_all(snippet.as_bytes())
+            .unwrap(),
+        None => print!("{}", snippet),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_error_model() {
+        let mut observations: Vec<(f64, f64)> = (0..100)
+            .map(|i| (1.0 + 2.0 * ((i as f64) / 99.0 - 0.5), 0.5))
+            .collect();
+        observations.push((100.0, 0.5));
+        // exact reports have no spread to deviate from
+        observations.push((1.5, 0.0));
+        let model = estimate_numeric_error_model(&observations).unwrap();
+        assert!((model.bias - 1.0).abs() < 0.05, "has wrong bias");
+        assert!(model.sigma_inflation > 1.0, "noise is underestimated");
+        assert!(
+            (model.outlier_rate - 1.0 / 101.0).abs() < 1e-9,
+            "has wrong outlier rate"
+        );
+        assert_eq!(model.spread_samples, 101);
+        assert_eq!(likelihood_weight(std::slice::from_ref(&model)), Some(1.0));
+        let mut outliers = observations.clone();
+        outliers.extend((0..20).map(|_| (-100.0, 0.5)));
+        let weight = likelihood_weight(&[estimate_numeric_error_model(&outliers).unwrap()]).unwrap();
+        assert!(weight < 0.5, "outliers not downweighted");
+    }
+}
@@ -1,7 +1,208 @@
+use std::collections::{BTreeMap, HashSet};
+
 use async_trait::async_trait;
+use futures::future::join_all;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    datasources::{cached_datasource::CacheStatistics, resilient_datasource::CircuitState},
+    search_result::SearchResult,
+};
+
+/// Query sent to the data sources
+#[derive(Debug, Clone, Default)]
+pub struct SearchRequest {
+    /// Free text describing the entity
+    pub query: String,
+    /// What else is known about the entity, e.g. its `barcode`, `brand` or `category`
+    pub hints: BTreeMap<String, String>,
+    /// Ask the backends directly instead of answering from a cache
+    pub bypass_cache: bool,
+    /// Record the contribution of each data source to the fused fields in the response
+    pub explain: bool,
+    /// Fields the caller is interested in. All fields if missing
+    pub fields: Option<Vec<RequestedField>>,
+    /// Current fused values of the fields the data source consumes, as found by the data sources queried before it
+    pub context: SearchResult,
+}
+
+/// Field requested from the data sources
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestedField {
+    /// Key of the field
+    pub name: String,
+    /// Unit numeric values are expected in. Missing for string fields
+    pub unit: Option<String>,
+}
+
+impl RequestedField {
+    pub fn new(name: String) -> Self {
+        RequestedField { name, unit: None }
+    }
+}
+
+impl SearchRequest {
+    /// Free text for sources that do not understand hints. The hint values are used if the query is empty
+    pub fn text(&self) -> String {
+        if self.query.is_empty() {
+            self.hints.values().cloned().collect::<Vec<_>>().join(" ")
+        } else {
+            self.query.clone()
+        }
+    }
+
+    /// Whether the caller is interested in the field with the given key
+    pub fn requests(&self, key: &str) -> bool {
+        match &self.fields {
+            Some(fields) => fields.iter().any(|f| f.name == key),
+            None => true,
+        }
+    }
+}
+
+impl From<String> for SearchRequest {
+    fn from(query: String) -> Self {
+        SearchRequest {
+            query,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub enum DataSourceStatus {
+    /// The data source is ready to answer queries
+    Available,
+    /// The data source could not be set up, with the reason
+    Unavailable(String),
+}
+
+impl DataSourceStatus {
+    /// Name of the status, without details
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataSourceStatus::Available => "Available",
+            DataSourceStatus::Unavailable(_) => "Unavailable",
+        }
+    }
+
+    pub fn details(&self) -> Option<String> {
+        match self {
+            DataSourceStatus::Available => None,
+            DataSourceStatus::Unavailable(reason) => Some(reason.clone()),
+        }
+    }
+}
+
+/// Entity a data source considers a possible match for a query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candidate {
+    /// Name of the entity, used to match the candidates of different sources
+    #[serde(default)]
+    pub label: Option<String>,
+    /// How well the entity matches the query, relative to the other candidates of the source
+    pub score: f64,
+    pub result: SearchResult,
+}
+
+impl From<SearchResult> for Candidate {
+    fn from(result: SearchResult) -> Self {
+        Candidate {
+            label: None,
+            score: 1.0,
+            result,
+        }
+    }
+}
+
+/// Reason why a data source did not return a result
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub enum DataSourceError {
+    /// The data source has no data for the query
+    NotFound,
+    /// The data source did not answer in time
+    Timeout,
+    /// The data source could not be reached
+    Transport(String),
+    /// The answer of the data source could not be parsed
+    Decode(String),
+    /// The answer of the data source contains a value that is not a valid distribution, or an error for the query
+    InvalidValue(String),
+    /// The data source is not queried, e.g. because it could not be set up or its circuit breaker is open
+    Unavailable(String),
+}
+
+impl DataSourceError {
+    /// Name of the error, without details
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataSourceError::NotFound => "NotFound",
+            DataSourceError::Timeout => "Timeout",
+            DataSourceError::Transport(_) => "Transport",
+            DataSourceError::Decode(_) => "Decode",
+            DataSourceError::InvalidValue(_) => "InvalidValue",
+            DataSourceError::Unavailable(_) => "Unavailable",
+        }
+    }
+
+    /// Whether the same query may succeed when repeated
+    pub fn is_transient(&self) -> bool {
+        matches!(self, DataSourceError::Timeout | DataSourceError::Transport(_))
+    }
+}
+
+impl std::fmt::Display for DataSourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataSourceError::NotFound => write!(f, "no data for the query"),
+            DataSourceError::Timeout => write!(f, "timed out"),
+            DataSourceError::Transport(e) => write!(f, "transport error: {}", e),
+            DataSourceError::Decode(e) => write!(f, "failed to decode answer: {}", e),
+            DataSourceError::InvalidValue(e) => write!(f, "invalid value: {}", e),
+            DataSourceError::Unavailable(e) => write!(f, "unavailable: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DataSourceError {}
 
-use crate::search_result::SearchResult;
 #[async_trait]
 pub trait DataSource{
-    async fn search(&self, query: String) -> Option<SearchResult>;
+    async fn search(&self, request: &SearchRequest) -> Result<SearchResult, DataSourceError>;
+
+    /// All entities matching the query. Defaults to the result of `search` as the only candidate
+    async fn search_candidates(
+        &self,
+        request: &SearchRequest,
+    ) -> Result<Vec<Candidate>, DataSourceError> {
+        Ok(vec![self.search(request).await?.into()])
+    }
+
+    /// Answers several queries, in the same order. Defaults to calling `search` for each of them concurrently
+    async fn search_many(
+        &self,
+        requests: &[SearchRequest],
+    ) -> Vec<Result<SearchResult, DataSourceError>> {
+        join_all(requests.iter().map(|request| self.search(request))).await
+    }
+
+    fn status(&self) -> DataSourceStatus {
+        DataSourceStatus::Available
+    }
+
+    /// Hits and misses of the cache in front of the data source, if it is cached
+    fn cache_statistics(&self) -> Option<CacheStatistics> {
+        None
+    }
+
+    /// Keys of the fields the data source can report, if it knows them in advance
+    fn provided_fields(&self) -> Option<HashSet<String>> {
+        None
+    }
+
+    /// State of the circuit breaker in front of the data source, if it has one
+    fn circuit_state(&self) -> Option<CircuitState> {
+        None
+    }
 }
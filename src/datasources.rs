@@ -1,3 +1,6 @@
+pub mod cached_datasource;
 pub mod grpc_datasource;
 pub mod rest_datasource;
-pub mod mock_datasource;
\ No newline at end of file
+pub mod mock_datasource;
+pub mod resilient_datasource;
+pub mod unavailable_datasource;
\ No newline at end of file
@@ -0,0 +1,292 @@
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use lru::LruCache;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::datasource::{Candidate, DataSource, DataSourceError, DataSourceStatus, SearchRequest};
+use crate::datasources::resilient_datasource::CircuitState;
+use crate::search_result::SearchResult;
+
+/// Number of queries answered from the cache and from the backend
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CacheStatistics {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Entry of the persistent cache
+#[derive(Serialize, Deserialize)]
+struct PersistedResult {
+    /// Seconds since the unix epoch at which the result was stored
+    stored_at: u64,
+    /// `None` if the source found nothing for the query
+    result: Option<SearchResult>,
+}
+
+/// Databases opened by this process, keyed by path. A database can only be opened once at a time, so sources
+/// sharing a path, and the engines built when the config is reloaded, reuse the open one
+fn open_store(path: PathBuf) -> Result<sled::Db, sled::Error> {
+    static STORES: OnceLock<Mutex<HashMap<PathBuf, sled::Db>>> = OnceLock::new();
+    let mut stores = STORES.get_or_init(Default::default).lock().unwrap();
+    if let Some(store) = stores.get(&path) {
+        return Ok(store.clone());
+    }
+    let store = sled::open(&path)?;
+    stores.insert(path, store.clone());
+    Ok(store)
+}
+
+/// Wraps a data source, answering repeated queries from an in-memory LRU cache
+/// and optionally from an embedded database that survives restarts.
+/// Results and queries the source found nothing for are cached, other errors are not
+pub struct CachedDataSource {
+    inner: Box<dyn DataSource + Sync + Send>,
+    /// Prefix of all keys, so several sources can share a persistent store
+    name: String,
+    ttl: Duration,
+    memory: Mutex<LruCache<String, (Instant, Option<SearchResult>)>>,
+    store: Option<sled::Db>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachedDataSource {
+    pub fn new(
+        name: String,
+        inner: Box<dyn DataSource + Sync + Send>,
+        ttl: Duration,
+        capacity: usize,
+    ) -> Self {
+        CachedDataSource {
+            inner,
+            name,
+            ttl,
+            memory: Mutex::new(LruCache::new(
+                NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN),
+            )),
+            store: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Additionally keeps the cached results in an embedded database at the given path
+    pub fn persist_to(&mut self, path: impl Into<PathBuf>) -> Result<(), sled::Error> {
+        self.store = Some(open_store(path.into())?);
+        Ok(())
+    }
+
+    /// Results depend on the hints, requested fields with their units and consumed fields,
+    /// so they are part of the key
+    fn key(&self, request: &SearchRequest) -> String {
+        let mut key = format!("{}\u{0}{}", self.name, request.query);
+        for (hint, value) in &request.hints {
+            key += &format!("\u{0}{}={}", hint, value);
+        }
+        if let Some(fields) = &request.fields {
+            let mut names: Vec<String> = fields
+                .iter()
+                .map(|f| match &f.unit {
+                    Some(unit) => format!("{}:{}", f.name, unit),
+                    None => f.name.clone(),
+                })
+                .collect();
+            names.sort_unstable();
+            key += "\u{0}";
+            key += &names.join(",");
+        }
+        if !request.context.is_empty() {
+            // converting to a JSON value sorts the maps
+            if let Ok(context) = serde_json::to_value(&request.context) {
+                key += &format!("\u{0}{}", context);
+            }
+        }
+        key
+    }
+
+    fn lookup(&self, key: &str) -> Option<Result<SearchResult, DataSourceError>> {
+        {
+            let mut memory = self.memory.lock().unwrap();
+            match memory.get(key) {
+                Some((stored_at, result)) if stored_at.elapsed() < self.ttl => {
+                    return Some(result.clone().ok_or(DataSourceError::NotFound))
+                }
+                Some(_) => {
+                    memory.pop(key);
+                }
+                None => {}
+            }
+        }
+        let stored = self.store.as_ref()?.get(key).ok()??;
+        let persisted: PersistedResult = serde_json::from_slice(&stored).ok()?;
+        let age = Duration::from_secs(unix_time().saturating_sub(persisted.stored_at));
+        if age >= self.ttl {
+            return None;
+        }
+        self.memory.lock().unwrap().put(
+            key.to_string(),
+            (Instant::now() - age, persisted.result.clone()),
+        );
+        Some(persisted.result.ok_or(DataSourceError::NotFound))
+    }
+
+    /// Stores results and `NotFound` errors, ignoring the other errors
+    fn store(&self, key: String, answer: &Result<SearchResult, DataSourceError>) {
+        let result = match answer {
+            Ok(result) => Some(result.clone()),
+            Err(DataSourceError::NotFound) => None,
+            Err(_) => return,
+        };
+        if let Some(store) = &self.store {
+            let persisted = PersistedResult {
+                stored_at: unix_time(),
+                result: result.clone(),
+            };
+            if let Ok(value) = serde_json::to_vec(&persisted) {
+                if let Err(e) = store.insert(key.as_bytes(), value) {
+                    eprintln!("Data source {}: failed to persist cached result: {}", self.name, e);
+                }
+            }
+        }
+        self.memory
+            .lock()
+            .unwrap()
+            .put(key, (Instant::now(), result));
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[async_trait]
+impl DataSource for CachedDataSource {
+    async fn search(&self, request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+        let key = self.key(request);
+        if !request.bypass_cache {
+            if let Some(answer) = self.lookup(&key) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return answer;
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let answer = self.inner.search(request).await;
+        self.store(key, &answer);
+        answer
+    }
+
+    /// Only the queries missing from the cache are passed on, as one batch
+    async fn search_many(
+        &self,
+        requests: &[SearchRequest],
+    ) -> Vec<Result<SearchResult, DataSourceError>> {
+        let keys: Vec<String> = requests.iter().map(|request| self.key(request)).collect();
+        let mut answers: Vec<Option<Result<SearchResult, DataSourceError>>> = requests
+            .iter()
+            .zip(&keys)
+            .map(|(request, key)| match request.bypass_cache {
+                true => None,
+                false => self.lookup(key),
+            })
+            .collect();
+        let missing: Vec<usize> = (0..requests.len()).filter(|i| answers[*i].is_none()).collect();
+        self.hits
+            .fetch_add((requests.len() - missing.len()) as u64, Ordering::Relaxed);
+        self.misses.fetch_add(missing.len() as u64, Ordering::Relaxed);
+        if !missing.is_empty() {
+            let missing_requests: Vec<SearchRequest> =
+                missing.iter().map(|i| requests[*i].clone()).collect();
+            let fetched = self.inner.search_many(&missing_requests).await;
+            for (i, answer) in missing.into_iter().zip(fetched) {
+                self.store(keys[i].clone(), &answer);
+                answers[i] = Some(answer);
+            }
+        }
+        answers
+            .into_iter()
+            .map(|a| a.unwrap_or(Err(DataSourceError::NotFound)))
+            .collect()
+    }
+
+    /// Candidates are not cached
+    async fn search_candidates(
+        &self,
+        request: &SearchRequest,
+    ) -> Result<Vec<Candidate>, DataSourceError> {
+        self.inner.search_candidates(request).await
+    }
+
+    fn status(&self) -> DataSourceStatus {
+        self.inner.status()
+    }
+
+    fn cache_statistics(&self) -> Option<CacheStatistics> {
+        Some(CacheStatistics {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        })
+    }
+
+    fn provided_fields(&self) -> Option<HashSet<String>> {
+        self.inner.provided_fields()
+    }
+
+    fn circuit_state(&self) -> Option<CircuitState> {
+        self.inner.circuit_state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{datasource::RequestedField, datasources::mock_datasource::MockDataSource};
+
+    #[tokio::test]
+    async fn hits_and_bypass() {
+        let inner = MockDataSource {
+            data: HashMap::from([("apple".to_string(), SearchResult::default())]),
+            records: vec![],
+        };
+        let cached = CachedDataSource::new("mock".to_string(), Box::new(inner), Duration::from_secs(60), 10);
+        let request = SearchRequest::from("apple".to_string());
+        assert!(cached.search(&request).await.is_ok());
+        assert!(cached.search(&request).await.is_ok());
+        let bypass = SearchRequest {
+            bypass_cache: true,
+            ..request
+        };
+        assert!(cached.search(&bypass).await.is_ok());
+        let pear = SearchRequest::from("pear".to_string());
+        assert_eq!(cached.search(&pear).await.unwrap_err(), DataSourceError::NotFound);
+        // queries the source found nothing for are cached too
+        assert_eq!(cached.search(&pear).await.unwrap_err(), DataSourceError::NotFound);
+        let in_unit = |unit: &str| SearchRequest {
+            fields: Some(vec![RequestedField {
+                name: "weight".to_string(),
+                unit: Some(unit.to_string()),
+            }]),
+            ..SearchRequest::from("apple".to_string())
+        };
+        assert!(cached.search(&in_unit("kg")).await.is_ok());
+        assert!(cached.search(&in_unit("g")).await.is_ok());
+        let statistics = cached.cache_statistics().unwrap();
+        assert_eq!(statistics.hits, 2, "has wrong number of hits");
+        assert_eq!(statistics.misses, 5, "has wrong number of misses");
+    }
+}
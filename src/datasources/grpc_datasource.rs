@@ -1,6 +1,10 @@
 use async_trait::async_trait;
+use futures::future::join_all;
 
-use crate::{datasource::DataSource, search_result::SearchResult};
+use crate::{
+    datasource::{DataSource, DataSourceError, SearchRequest},
+    search_result::SearchResult,
+};
 
 pub mod grpc_ds {
     tonic::include_proto!("datasource");
@@ -55,41 +59,184 @@ pub fn convert_numeric_field_value(
     }
 }
 
+pub fn to_grpc_numeric_field_value(
+    value: &crate::numeric::NumericFieldValue,
+) -> Option<grpc_ds::numeric_field_value::Value> {
+    use crate::numeric::NumericFieldValue;
+    Some(match value {
+        NumericFieldValue::Exact(value) => grpc_ds::numeric_field_value::Value::Exact(grpc_ds::Exact {
+            value: *value as f32,
+        }),
+        NumericFieldValue::Normal { sigma, mu } => {
+            grpc_ds::numeric_field_value::Value::Normal(grpc_ds::Normal {
+                mu: *mu as f32,
+                sigma: *sigma as f32,
+            })
+        }
+        NumericFieldValue::Uniform { min, max } => {
+            grpc_ds::numeric_field_value::Value::Uniform(grpc_ds::Uniform {
+                min: *min as f32,
+                max: *max as f32,
+            })
+        }
+        NumericFieldValue::Combination {
+            components,
+            scaling_factor,
+            mu,
+            sigma,
+        } => grpc_ds::numeric_field_value::Value::Combination(grpc_ds::Combination {
+            values: components
+                .iter()
+                .map(|c| grpc_ds::NumericFieldValue {
+                    value: to_grpc_numeric_field_value(c),
+                })
+                .collect(),
+            scaling_factor: *scaling_factor as f32,
+            mu: *mu as f32,
+            sigma: *sigma as f32,
+        }),
+        NumericFieldValue::Error => return None,
+    })
+}
+
+pub fn to_grpc_string_field_value(
+    value: &crate::string::StringFieldValue,
+) -> Option<grpc_ds::string_field_value::Value> {
+    use crate::string::StringFieldValue;
+    Some(match value {
+        StringFieldValue::Exact(value) => grpc_ds::string_field_value::Value::Exact(value.clone()),
+        StringFieldValue::Distribution(d) => {
+            grpc_ds::string_field_value::Value::Distribution(grpc_ds::Distribution {
+                values: d.iter().map(|(k, v)| (k.clone(), *v as f32)).collect(),
+            })
+        }
+        StringFieldValue::Error => return None,
+    })
+}
+
+/// Converts fused values into the message passed to data sources consuming them
+fn to_grpc_search_result(result: &SearchResult) -> grpc_ds::SearchResult {
+    grpc_ds::SearchResult {
+        numeric_fields: result
+            .numeric_fields
+            .iter()
+            .filter_map(|(k, v)| {
+                to_grpc_numeric_field_value(v)
+                    .map(|v| (k.clone(), grpc_ds::NumericFieldValue { value: Some(v) }))
+            })
+            .collect(),
+        string_fields: result
+            .string_fields
+            .iter()
+            .filter_map(|(k, v)| {
+                to_grpc_string_field_value(v)
+                    .map(|v| (k.clone(), grpc_ds::StringFieldValue { value: Some(v) }))
+            })
+            .collect(),
+        ids: result.ids.clone().into_iter().collect(),
+    }
+}
+
 pub struct GrpcDataSource {
     client: grpc_ds::data_source_client::DataSourceClient<tonic::transport::Channel>,
 }
 
+fn to_grpc_request(request: &SearchRequest) -> grpc_ds::SearchRequest {
+    grpc_ds::SearchRequest {
+        query: request.text(),
+        hints: request.hints.clone().into_iter().collect(),
+        fields: request
+            .fields
+            .iter()
+            .flatten()
+            .map(|f| grpc_ds::RequestedField {
+                name: f.name.clone(),
+                unit: f.unit.clone().unwrap_or_default(),
+            })
+            .collect(),
+        context: (!request.context.is_empty()).then(|| to_grpc_search_result(&request.context)),
+    }
+}
+
+fn convert_status(status: tonic::Status) -> DataSourceError {
+    match status.code() {
+        tonic::Code::NotFound => DataSourceError::NotFound,
+        tonic::Code::DeadlineExceeded => DataSourceError::Timeout,
+        _ => DataSourceError::Transport(status.to_string()),
+    }
+}
+
+fn convert_search_result(result: grpc_ds::SearchResult) -> Result<SearchResult, DataSourceError> {
+    let result = SearchResult {
+        ids: result.ids.into_iter().collect(),
+        numeric_fields: result
+            .numeric_fields
+            .into_iter()
+            .filter_map(|v| {
+                v.1.value
+                    .map(|n_value| (v.0, convert_numeric_field_value(n_value)))
+            })
+            .collect(),
+        string_fields: result
+            .string_fields
+            .into_iter()
+            .filter_map(|v| {
+                v.1.value
+                    .map(|s_value| (v.0, convert_string_field_value(s_value)))
+            })
+            .collect(),
+    };
+    result.validate()?;
+    Ok(result)
+}
+
 #[async_trait]
 impl DataSource for GrpcDataSource {
-    async fn search(&self, query: String) -> Option<SearchResult> {
-        let response = match self
+    async fn search(&self, request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+        let response = self
             .client
             .clone()
-            .search(grpc_ds::SearchRequest { query })
+            .search(to_grpc_request(request))
             .await
-        {
-            Ok(r) => r,
-            Err(_) => return None,
+            .map_err(convert_status)?;
+        convert_search_result(response.into_inner())
+    }
+
+    /// Sends the queries in one call, or one by one if the server does not implement batches
+    async fn search_many(
+        &self,
+        requests: &[SearchRequest],
+    ) -> Vec<Result<SearchResult, DataSourceError>> {
+        let response = self
+            .client
+            .clone()
+            .search_many(grpc_ds::SearchManyRequest {
+                requests: requests.iter().map(to_grpc_request).collect(),
+            })
+            .await;
+        let answers = match response {
+            Ok(response) => response.into_inner().answers,
+            Err(status) if status.code() == tonic::Code::Unimplemented => {
+                return join_all(requests.iter().map(|request| self.search(request))).await
+            }
+            Err(status) => return vec![Err(convert_status(status)); requests.len()],
         };
-        let result = response.into_inner();
-        Some(SearchResult {
-            numeric_fields: result
-                .numeric_fields
-                .into_iter()
-                .filter_map(|v| {
-                    v.1.value
-                        .map(|n_value| (v.0, convert_numeric_field_value(n_value)))
-                })
-                .collect(),
-            string_fields: result
-                .string_fields
-                .into_iter()
-                .filter_map(|v| {
-                    v.1.value
-                        .map(|s_value| (v.0, convert_string_field_value(s_value)))
-                })
-                .collect(),
-        })
+        if answers.len() != requests.len() {
+            let error = DataSourceError::Decode(format!(
+                "{} answers to {} queries",
+                answers.len(),
+                requests.len()
+            ));
+            return vec![Err(error); requests.len()];
+        }
+        answers
+            .into_iter()
+            .map(|answer| match answer.answer {
+                Some(grpc_ds::batch_answer::Answer::Result(result)) => convert_search_result(result),
+                Some(grpc_ds::batch_answer::Answer::Error(e)) => Err(DataSourceError::InvalidValue(e)),
+                None => Err(DataSourceError::NotFound),
+            })
+            .collect()
     }
 }
 
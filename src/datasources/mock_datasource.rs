@@ -1,36 +1,246 @@
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
 use async_trait::async_trait;
+use schemars::JsonSchema;
 use serde::Deserialize;
 
-use crate::{search_result::SearchResult, datasource::DataSource};
-#[derive(Deserialize)]
+use crate::{
+    datasource::{Candidate, DataSource, DataSourceError, SearchRequest},
+    search_result::SearchResult,
+};
+/// Data file of a Mock source, answering queries with fixed results
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct MockDataSource {
+    /// Results keyed by the free text query, or by a regular expression matching it
+    #[serde(default)]
     pub data: HashMap<String, SearchResult>,
+    /// Results matched on the hints of structured queries. Tried before `data`
+    #[serde(default)]
+    pub records: Vec<MockRecord>,
 }
+
+/// Result of the mock data source for the queries matching all its keys
+#[derive(Deserialize, JsonSchema)]
+pub struct MockRecord {
+    /// Values of the hints the record matches, as exact values or regular expressions.
+    /// The key `query` is matched against the free text of the query. Keys missing from the hints
+    /// are matched against the most probable value of the consumed string field of the same name
+    pub keys: HashMap<String, String>,
+    /// Name of the entity described by the record
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Match score of the record when several candidates are searched. Defaults to 1
+    #[serde(default = "default_score")]
+    pub score: f64,
+    #[serde(flatten)]
+    pub result: SearchResult,
+}
+
+fn default_score() -> f64 {
+    1.0
+}
+
+/// Score of a candidate whose free text key only matches the query as a regular expression
+const PATTERN_MATCH_SCORE: f64 = 0.5;
+
+fn is_match(pattern: &str, value: &str) -> bool {
+    pattern == value
+        || regex::Regex::new(pattern)
+            .map(|r| r.is_match(value))
+            .unwrap_or(false)
+}
+
+impl MockRecord {
+    /// Whether the record is only keyed on the free text of the query
+    fn only_matches_query(&self) -> bool {
+        self.keys.keys().all(|key| key == "query")
+    }
+
+    fn matches(&self, request: &SearchRequest) -> bool {
+        !self.keys.is_empty()
+            && self.keys.iter().all(|(key, pattern)| {
+                let value = match key.as_str() {
+                    "query" => Some(&request.query),
+                    _ => request.hints.get(key).or_else(|| {
+                        let consumed = request.context.string_fields.get(key)?;
+                        consumed.most_probable().map(|(value, _)| value)
+                    }),
+                };
+                value.is_some_and(|v| is_match(pattern, v))
+            })
+    }
+}
+
 #[async_trait]
 impl DataSource for MockDataSource {
-    async fn search(&self, query: String) -> Option<SearchResult> {
-        if let Some(res) =self.data.get(&query) {
-            return Some(res.clone())
+    /// Records matching on hints come first, then the free text keys of `data`. Records keyed only on the query
+    /// are alternatives for `search_candidates`, used by `search` if no key of `data` matches
+    async fn search(&self, request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+        let (query_records, hint_records): (Vec<&MockRecord>, Vec<&MockRecord>) = self
+            .records
+            .iter()
+            .filter(|r| r.matches(request))
+            .partition(|r| r.only_matches_query());
+        if let Some(record) = hint_records.first() {
+            return Ok(filter_fields(&record.result, request));
+        }
+        let query = &request.text();
+        if let Some(res) =self.data.get(query) {
+            return Ok(filter_fields(res, request))
         }
         for (key, value) in self.data.iter() {
-            if regex::Regex::new(&key).map(|r| r.is_match(&query)).unwrap_or(false){
-                return Some(value.clone())
+            if is_match(key, query) {
+                return Ok(filter_fields(value, request))
             }
         }
-        None
+        match query_records.first() {
+            Some(record) => Ok(filter_fields(&record.result, request)),
+            None => Err(DataSourceError::NotFound),
+        }
+    }
+
+    async fn search_candidates(
+        &self,
+        request: &SearchRequest,
+    ) -> Result<Vec<Candidate>, DataSourceError> {
+        let query = request.text();
+        let mut candidates: Vec<Candidate> = self
+            .records
+            .iter()
+            .filter(|r| r.matches(request))
+            .map(|r| Candidate {
+                label: r.label.clone(),
+                score: r.score,
+                result: filter_fields(&r.result, request),
+            })
+            .collect();
+        for (key, value) in self.data.iter() {
+            let (label, score) = if *key == query {
+                (Some(key.clone()), 1.0)
+            } else if is_match(key, &query) {
+                (None, PATTERN_MATCH_SCORE)
+            } else {
+                continue;
+            };
+            candidates.push(Candidate {
+                label,
+                score,
+                result: filter_fields(value, request),
+            });
+        }
+        if candidates.is_empty() {
+            return Err(DataSourceError::NotFound);
+        }
+        Ok(candidates)
+    }
+
+    fn provided_fields(&self) -> Option<HashSet<String>> {
+        Some(
+            self.data
+                .values()
+                .chain(self.records.iter().map(|r| &r.result))
+                .flat_map(|r| r.numeric_fields.keys().chain(r.string_fields.keys()))
+                .cloned()
+                .collect(),
+        )
     }
 }
 
+/// Keeps only the fields requested by the caller
+fn filter_fields(result: &SearchResult, request: &SearchRequest) -> SearchResult {
+    let mut result = result.clone();
+    result.numeric_fields.retain(|key, _| request.requests(key));
+    result.string_fields.retain(|key, _| request.requests(key));
+    result
+}
+
 impl MockDataSource {
-    pub fn load_from_file(data_file: impl Into<PathBuf>) -> Self {
+    pub fn load_from_file(data_file: impl Into<PathBuf>) -> Result<Self, config::ConfigError> {
         config::Config::builder()
             .add_source(config::File::from(data_file.into()))
-            .build()
-            .unwrap()
+            .build()?
             .try_deserialize()
-            .unwrap()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{datasource::RequestedField, numeric::NumericFieldValue, string::StringFieldValue};
+
+    fn result(color: &str) -> SearchResult {
+        SearchResult {
+            string_fields: HashMap::from([(
+                "color".to_string(),
+                StringFieldValue::Exact(color.to_string()),
+            )]),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn structured_query() {
+        let source = MockDataSource {
+            data: HashMap::from([("apple".to_string(), result("red"))]),
+            records: vec![MockRecord {
+                keys: HashMap::from([
+                    ("query".to_string(), "apple".to_string()),
+                    ("category".to_string(), "juice.*".to_string()),
+                ]),
+                label: None,
+                score: 1.0,
+                result: result("yellow"),
+            }],
+        };
+        let mut request = SearchRequest::from("apple".to_string());
+        let color = |r: SearchResult| r.string_fields["color"].clone();
+        assert!(matches!(color(source.search(&request).await.unwrap()), StringFieldValue::Exact(c) if c == "red"));
+        request.hints.insert("category".to_string(), "juices".to_string());
+        assert!(matches!(color(source.search(&request).await.unwrap()), StringFieldValue::Exact(c) if c == "yellow"));
+    }
+
+    #[tokio::test]
+    async fn query_records_fall_back() {
+        let source = MockDataSource {
+            data: HashMap::from([("apple".to_string(), result("red"))]),
+            records: vec![MockRecord {
+                keys: HashMap::from([("query".to_string(), "apple|pear".to_string())]),
+                label: None,
+                score: 1.0,
+                result: result("yellow"),
+            }],
+        };
+        let color = |r: SearchResult| r.string_fields["color"].clone();
+        let apple = SearchRequest::from("apple".to_string());
+        assert!(matches!(color(source.search(&apple).await.unwrap()), StringFieldValue::Exact(c) if c == "red"));
+        let pear = SearchRequest::from("pear".to_string());
+        assert!(matches!(color(source.search(&pear).await.unwrap()), StringFieldValue::Exact(c) if c == "yellow"));
+    }
+
+    #[tokio::test]
+    async fn requested_fields() {
+        let mut apple = result("red");
+        apple
+            .numeric_fields
+            .insert("weight".to_string(), NumericFieldValue::Exact(0.2));
+        let source = MockDataSource {
+            data: HashMap::from([("apple".to_string(), apple)]),
+            records: vec![],
+        };
+        let weight = SearchRequest {
+            fields: Some(vec![RequestedField::new("weight".to_string())]),
+            ..SearchRequest::from("apple".to_string())
+        };
+        let answer = source.search(&weight).await.unwrap();
+        assert_eq!(answer.numeric_fields.keys().collect::<Vec<_>>(), ["weight"]);
+        assert!(answer.string_fields.is_empty());
+        // without a list of fields, all of them are returned
+        let all = source.search(&SearchRequest::from("apple".to_string())).await.unwrap();
+        assert!(all.numeric_fields.contains_key("weight"));
+        assert!(all.string_fields.contains_key("color"));
+    }
+}
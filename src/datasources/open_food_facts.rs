@@ -3,18 +3,22 @@ use std::collections::HashMap;
 use async_trait::async_trait;
 use openapi::apis::configuration::Configuration;
 
-use crate::{datasource::DataSource, numeric::NumericFieldValue, search_result::SearchResult};
+use crate::{
+    datasource::{DataSource, DataSourceError, SearchRequest},
+    numeric::NumericFieldValue,
+    search_result::SearchResult,
+};
 
 pub struct OpenFoodFactsDataSource {
     config: Configuration,
 }
 #[async_trait]
 impl DataSource for OpenFoodFactsDataSource {
-    async fn search(&self, query: String) -> Option<SearchResult> {
+    async fn search(&self, request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
         let raw_res = openapi::apis::read_requests_api::get_search(
             &self.config,
             openapi::apis::read_requests_api::GetSearchParams {
-                categories_tags_en: Some(query),
+                categories_tags_en: Some(request.text()),
                 labels_tags_en: None,
                 fields: Some("code,product_name,nutriscore_data,nutriments".into()),
             },
@@ -28,10 +32,11 @@ impl DataSource for OpenFoodFactsDataSource {
                     Some(products) => {
                         let product = match products.first() {
                             Some(p) => p.clone(),
-                            None => return None,
+                            None => return Err(DataSourceError::NotFound),
                         };
-                        if let Some(energy) = product.nutriments?.energy {
-                            return Some(SearchResult {
+                        if let Some(energy) = product.nutriments.and_then(|n| n.energy) {
+                            return Ok(SearchResult {
+                                ids: HashMap::new(),
                                 numeric_fields: HashMap::from([(
                                     "energy_density".to_string(),
                                     NumericFieldValue::Normal {
@@ -47,11 +52,10 @@ impl DataSource for OpenFoodFactsDataSource {
                     }
                     None => {}
                 }
-                Some(SearchResult::default())
+                Ok(SearchResult::default())
             }
             Err(e) => {
-                println!("{:?}", e);
-                None
+                Err(DataSourceError::Transport(e.to_string()))
             }
         }
     }
@@ -0,0 +1,354 @@
+use std::{
+    collections::HashSet,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    datasource::{Candidate, DataSource, DataSourceError, DataSourceStatus, SearchRequest},
+    datasources::cached_datasource::CacheStatistics,
+    search_engine_config::ResilienceConfig,
+    search_result::SearchResult,
+};
+
+/// State of the circuit breaker in front of a data source
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub enum CircuitState {
+    /// Queries are forwarded to the data source
+    Closed,
+    /// The data source failed repeatedly, queries fail without being forwarded
+    Open,
+    /// The next query is forwarded to probe whether the data source recovered
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "Closed",
+            CircuitState::Open => "Open",
+            CircuitState::HalfOpen => "HalfOpen",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    /// Time at which the breaker last opened, if it is not closed
+    opened_at: Option<Instant>,
+    /// Start of the probing query in flight while half-open. A probe older than the open duration
+    /// is considered lost, e.g. because the search gave up waiting for it
+    probing_since: Option<Instant>,
+}
+
+/// Wraps a remote data source, retrying failed queries with exponential backoff
+/// and failing fast while the source is considered dead.
+/// Only transient errors, i.e. timeouts and transport errors, are retried and count as failures.
+/// Queries abandoned before they complete count as failures too, so that hanging sources trip the breaker
+pub struct ResilientDataSource {
+    inner: Box<dyn DataSource + Sync + Send>,
+    name: String,
+    config: ResilienceConfig,
+    /// Time after which an attempt is abandoned and fails with a timeout
+    timeout: Option<Duration>,
+    breaker: Mutex<Breaker>,
+}
+
+/// Records a failure if the query is dropped before it completes,
+/// e.g. because the search stopped waiting for a hanging source
+struct Pending<'a> {
+    source: &'a ResilientDataSource,
+    probe: bool,
+    done: bool,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.source.record_failure(self.probe);
+        }
+    }
+}
+
+impl ResilientDataSource {
+    pub fn new(
+        name: String,
+        inner: Box<dyn DataSource + Sync + Send>,
+        config: ResilienceConfig,
+        timeout: Option<Duration>,
+    ) -> Self {
+        ResilientDataSource {
+            inner,
+            name,
+            config,
+            timeout,
+            breaker: Mutex::new(Breaker::default()),
+        }
+    }
+
+    fn open_duration(&self) -> Duration {
+        Duration::from_millis(self.config.open_duration_ms)
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let breaker = self.breaker.lock().unwrap();
+        match breaker.opened_at {
+            None => CircuitState::Closed,
+            Some(t) if t.elapsed() < self.open_duration() => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Decides whether a query may be forwarded. Returns whether it is a probe of a half-open breaker
+    fn admit(&self) -> Option<bool> {
+        let mut breaker = self.breaker.lock().unwrap();
+        match breaker.opened_at {
+            None => Some(false),
+            Some(t) if t.elapsed() < self.open_duration() => None,
+            Some(_)
+                if breaker
+                    .probing_since
+                    .is_some_and(|t| t.elapsed() < self.open_duration()) =>
+            {
+                None
+            }
+            Some(_) => {
+                breaker.probing_since = Some(Instant::now());
+                Some(true)
+            }
+        }
+    }
+
+    /// Runs a query through the circuit breaker, retrying it on transient errors.
+    /// Attempts exceeding the timeout fail with [DataSourceError::Timeout]
+    async fn call<T, F, Fut>(&self, query: F) -> Result<T, DataSourceError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, DataSourceError>>,
+    {
+        let probe = self
+            .admit()
+            .ok_or_else(|| DataSourceError::Unavailable("circuit breaker open".to_string()))?;
+        // a probe only checks whether the source recovered, retrying it would delay the search
+        let retries = if probe { 0 } else { self.config.retries };
+        let mut backoff = Duration::from_millis(self.config.initial_backoff_ms);
+        let mut attempt = 0;
+        let mut pending = Pending {
+            source: self,
+            probe,
+            done: false,
+        };
+        loop {
+            let answer = match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, query())
+                    .await
+                    .unwrap_or(Err(DataSourceError::Timeout)),
+                None => query().await,
+            };
+            match answer {
+                Err(e) if e.is_transient() => {
+                    if attempt == retries {
+                        pending.done = true;
+                        self.record_failure(probe);
+                        return Err(e);
+                    }
+                }
+                // the source is up, even if it has no valid data for the query
+                answer => {
+                    pending.done = true;
+                    self.record_success();
+                    return answer;
+                }
+            }
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff = backoff
+                .mul_f64(self.config.backoff_multiplier)
+                .min(Duration::from_millis(self.config.max_backoff_ms));
+        }
+    }
+
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock().unwrap();
+        if breaker.opened_at.is_some() {
+            eprintln!("Data source {}: circuit breaker closed", self.name);
+        }
+        *breaker = Breaker::default();
+    }
+
+    fn record_failure(&self, probe: bool) {
+        let mut breaker = self.breaker.lock().unwrap();
+        breaker.consecutive_failures += 1;
+        breaker.probing_since = None;
+        if probe || breaker.consecutive_failures >= self.config.failure_threshold {
+            if breaker.opened_at.is_none() {
+                eprintln!(
+                    "Data source {}: circuit breaker opened after {} consecutive failures",
+                    self.name, breaker.consecutive_failures
+                );
+            }
+            breaker.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[async_trait]
+impl DataSource for ResilientDataSource {
+    async fn search(&self, request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+        self.call(|| self.inner.search(request)).await
+    }
+
+    async fn search_candidates(
+        &self,
+        request: &SearchRequest,
+    ) -> Result<Vec<Candidate>, DataSourceError> {
+        self.call(|| self.inner.search_candidates(request)).await
+    }
+
+    /// The batch counts as one query, failing if all its queries fail with a transient error
+    async fn search_many(
+        &self,
+        requests: &[SearchRequest],
+    ) -> Vec<Result<SearchResult, DataSourceError>> {
+        let answers = self
+            .call(|| async {
+                let answers = self.inner.search_many(requests).await;
+                match answers.first() {
+                    Some(Err(e))
+                        if answers
+                            .iter()
+                            .all(|a| a.as_ref().is_err_and(DataSourceError::is_transient)) =>
+                    {
+                        Err(e.clone())
+                    }
+                    _ => Ok(answers),
+                }
+            })
+            .await;
+        match answers {
+            Ok(answers) => answers,
+            Err(e) => vec![Err(e); requests.len()],
+        }
+    }
+
+    fn status(&self) -> DataSourceStatus {
+        match self.state() {
+            CircuitState::Open => DataSourceStatus::Unavailable(format!(
+                "circuit breaker open after {} consecutive failures",
+                self.breaker.lock().unwrap().consecutive_failures
+            )),
+            _ => self.inner.status(),
+        }
+    }
+
+    fn cache_statistics(&self) -> Option<CacheStatistics> {
+        self.inner.cache_statistics()
+    }
+
+    fn provided_fields(&self) -> Option<HashSet<String>> {
+        self.inner.provided_fields()
+    }
+
+    fn circuit_state(&self) -> Option<CircuitState> {
+        Some(self.state())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    /// Fails the first `failures` queries, then answers
+    struct Flaky {
+        failures: u32,
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl DataSource for Flaky {
+        async fn search(&self, _request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+            let call = self.calls.fetch_add(1, Ordering::Relaxed);
+            if call < self.failures {
+                return Err(DataSourceError::Transport("connection refused".to_string()));
+            }
+            Ok(SearchResult::default())
+        }
+    }
+
+    /// Never answers
+    struct Hanging;
+
+    #[async_trait]
+    impl DataSource for Hanging {
+        async fn search(&self, _request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+            std::future::pending().await
+        }
+    }
+
+    fn config(retries: u32) -> ResilienceConfig {
+        ResilienceConfig {
+            retries,
+            initial_backoff_ms: 1,
+            backoff_multiplier: 2.0,
+            max_backoff_ms: 10,
+            failure_threshold: 2,
+            open_duration_ms: 50,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries() {
+        let flaky = Flaky {
+            failures: 2,
+            calls: AtomicU32::new(0),
+        };
+        let source = ResilientDataSource::new("flaky".to_string(), Box::new(flaky), config(2), None);
+        assert!(source.search(&SearchRequest::default()).await.is_ok());
+        assert_eq!(source.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker() {
+        let flaky = Flaky {
+            failures: 3,
+            calls: AtomicU32::new(0),
+        };
+        let source = ResilientDataSource::new("flaky".to_string(), Box::new(flaky), config(0), None);
+        let request = SearchRequest::default();
+        assert!(source.search(&request).await.is_err());
+        assert_eq!(source.state(), CircuitState::Closed);
+        assert!(source.search(&request).await.is_err());
+        assert_eq!(source.state(), CircuitState::Open);
+        // fails fast without querying the source
+        assert!(source.search(&request).await.is_err());
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(source.state(), CircuitState::HalfOpen);
+        // the failing probe opens the breaker again
+        assert!(source.search(&request).await.is_err());
+        assert_eq!(source.state(), CircuitState::Open);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(source.search(&request).await.is_ok());
+        assert_eq!(source.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn hanging_source() {
+        let timeout = Some(Duration::from_millis(10));
+        let source = ResilientDataSource::new("hanging".to_string(), Box::new(Hanging), config(1), timeout);
+        let request = SearchRequest::default();
+        assert_eq!(source.search(&request).await.unwrap_err(), DataSourceError::Timeout);
+        assert_eq!(source.state(), CircuitState::Closed);
+        // a query abandoned by the search counts as failed too
+        let abandoned = tokio::time::timeout(Duration::from_millis(5), source.search(&request)).await;
+        assert!(abandoned.is_err());
+        assert_eq!(source.state(), CircuitState::Open);
+    }
+}
@@ -1,23 +1,75 @@
 use async_trait::async_trait;
 
-use crate::datasource::DataSource;
+use crate::datasource::{DataSource, DataSourceError, RequestedField, SearchRequest};
 use crate::search_result::SearchResult;
 
 pub struct RestDatasource {
     base_url: String,
+    client: reqwest::Client,
 }
 impl RestDatasource {
     pub fn new(base_url: String) -> Self {
-        RestDatasource { base_url }
+        RestDatasource {
+            base_url,
+            client: reqwest::Client::new(),
+        }
     }
 }
 
+/// Requested fields as a comma separated list, with the unit of numeric fields after a colon, e.g. `weight:kg,color`
+fn fields_parameter(fields: &[RequestedField]) -> String {
+    fields
+        .iter()
+        .map(|f| match &f.unit {
+            Some(unit) => format!("{}:{}", f.name, unit),
+            None => f.name.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 #[async_trait]
 impl DataSource for RestDatasource {
-    async fn search(&self, query: String) -> Option<SearchResult> {
-        let response = reqwest::get(format!("{}/search?query={}", self.base_url, query))
+    async fn search(&self, request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+        let mut parameters = vec![("query".to_string(), request.text())];
+        if let Some(fields) = &request.fields {
+            parameters.push(("fields".to_string(), fields_parameter(fields)));
+        }
+        for (key, value) in &request.hints {
+            parameters.push((format!("hint.{}", key), value.clone()));
+        }
+        if !request.context.is_empty() {
+            let context = serde_json::to_string(&request.context)
+                .map_err(|e| DataSourceError::InvalidValue(e.to_string()))?;
+            parameters.push(("context".to_string(), context));
+        }
+        let response = self
+            .client
+            .get(format!("{}/search", self.base_url))
+            .query(&parameters)
+            .send()
+            .await
+            .map_err(convert_error)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(DataSourceError::NotFound);
+        }
+        let result: SearchResult = response
+            .error_for_status()
+            .map_err(convert_error)?
+            .json()
             .await
-            .ok()?;
-        response.json().await.ok()
+            .map_err(convert_error)?;
+        result.validate()?;
+        Ok(result)
+    }
+}
+
+fn convert_error(e: reqwest::Error) -> DataSourceError {
+    if e.is_timeout() {
+        DataSourceError::Timeout
+    } else if e.is_decode() {
+        DataSourceError::Decode(e.to_string())
+    } else {
+        DataSourceError::Transport(e.to_string())
     }
 }
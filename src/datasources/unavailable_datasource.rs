@@ -0,0 +1,20 @@
+use async_trait::async_trait;
+
+use crate::datasource::{DataSource, DataSourceError, DataSourceStatus, SearchRequest};
+use crate::search_result::SearchResult;
+
+/// Placeholder for a configured data source that could not be set up
+pub struct UnavailableDataSource {
+    pub reason: String,
+}
+
+#[async_trait]
+impl DataSource for UnavailableDataSource {
+    async fn search(&self, _request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+        Err(DataSourceError::Unavailable(self.reason.clone()))
+    }
+
+    fn status(&self) -> DataSourceStatus {
+        DataSourceStatus::Unavailable(self.reason.clone())
+    }
+}
@@ -0,0 +1,576 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field as ArrowField, Schema};
+use futures::{stream, StreamExt};
+use parquet::arrow::ArrowWriter;
+
+use crate::{
+    datasource::{RequestedField, SearchRequest},
+    search_engine::{FieldType, FieldValue, SearchEngine, SearchResponse},
+};
+
+/// Number of rows written to a Parquet file at once
+const PARQUET_ROW_GROUP: usize = 1024;
+
+/// Searches the queries of a file and writes the fused fields of each to another file
+#[derive(clap::Args, Debug)]
+pub struct EnrichArgs {
+    /// CSV file with a header row, or JSONL file with one object per line, holding one query per row
+    pub input: PathBuf,
+    /// File the results are written to, as CSV, JSONL or Parquet depending on its extension
+    pub output: PathBuf,
+    /// Column holding the free text query. The other columns are passed as hints
+    #[arg(long, default_value = "query")]
+    pub query_column: String,
+    /// Comma separated keys of the fields to output. Defaults to all fields
+    #[arg(long, value_delimiter = ',')]
+    pub fields: Option<Vec<String>>,
+    /// Number of queries searched at the same time
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+    /// Probability covered by the central interval written for numeric fields
+    #[arg(long, default_value_t = 0.9)]
+    pub interval: f64,
+    /// Query the data sources even if their results are cached
+    #[arg(long)]
+    pub no_cache: bool,
+}
+
+#[derive(Debug)]
+pub enum EnrichError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    Parquet(parquet::errors::ParquetError),
+    /// The input or output file has an unsupported extension or content
+    Format(String),
+}
+
+impl std::fmt::Display for EnrichError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnrichError::Io(e) => write!(f, "{}", e),
+            EnrichError::Csv(e) => write!(f, "invalid CSV: {}", e),
+            EnrichError::Json(e) => write!(f, "invalid JSON: {}", e),
+            EnrichError::Parquet(e) => write!(f, "failed to write Parquet: {}", e),
+            EnrichError::Format(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EnrichError {}
+
+impl From<std::io::Error> for EnrichError {
+    fn from(e: std::io::Error) -> Self {
+        EnrichError::Io(e)
+    }
+}
+
+impl From<csv::Error> for EnrichError {
+    fn from(e: csv::Error) -> Self {
+        EnrichError::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for EnrichError {
+    fn from(e: serde_json::Error) -> Self {
+        EnrichError::Json(e)
+    }
+}
+
+impl From<parquet::errors::ParquetError> for EnrichError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        EnrichError::Parquet(e)
+    }
+}
+
+impl From<arrow_schema::ArrowError> for EnrichError {
+    fn from(e: arrow_schema::ArrowError) -> Self {
+        EnrichError::Parquet(e.into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+impl Format {
+    fn of(path: &Path) -> Result<Self, EnrichError> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Ok(Format::Csv),
+            Some("jsonl") | Some("ndjson") => Ok(Format::Jsonl),
+            Some("parquet") => Ok(Format::Parquet),
+            _ => Err(EnrichError::Format(format!(
+                "{}: unknown format, expected a .csv, .jsonl or .parquet file",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// Row of the input file, keyed by column
+type InputRow = BTreeMap<String, String>;
+
+/// Reads the columns and rows of a CSV or JSONL file. Non-string JSON values are kept as JSON text
+fn read_input(path: &Path) -> Result<(Vec<String>, Vec<InputRow>), EnrichError> {
+    let mut columns: Vec<String> = vec![];
+    let mut rows = vec![];
+    match Format::of(path)? {
+        Format::Csv => {
+            let mut reader = csv::Reader::from_path(path)?;
+            columns = reader.headers()?.iter().map(str::to_string).collect();
+            for record in reader.records() {
+                rows.push(columns.iter().cloned().zip(record?.iter().map(str::to_string)).collect());
+            }
+        }
+        Format::Jsonl => {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&line)?;
+                let mut row = InputRow::new();
+                for (key, value) in object {
+                    if !columns.contains(&key) {
+                        columns.push(key.clone());
+                    }
+                    let value = match value {
+                        serde_json::Value::String(s) => s,
+                        serde_json::Value::Null => continue,
+                        other => other.to_string(),
+                    };
+                    row.insert(key, value);
+                }
+                rows.push(row);
+            }
+        }
+        Format::Parquet => {
+            return Err(EnrichError::Format(
+                "Parquet is only supported as output format".to_string(),
+            ))
+        }
+    }
+    Ok((columns, rows))
+}
+
+/// Value of an output cell. Missing if the field was not found or could not be fused
+#[derive(Debug, Clone)]
+enum Cell {
+    Text(Option<String>),
+    Number(Option<f64>),
+}
+
+/// Output columns describing one fused field
+fn field_columns(key: &str, field_type: &FieldType) -> Vec<(String, DataType)> {
+    match field_type {
+        FieldType::Float { .. } => ["mean", "sigma", "lower", "upper"]
+            .iter()
+            .map(|suffix| (format!("{}_{}", key, suffix), DataType::Float64))
+            .collect(),
+        FieldType::String => vec![
+            (format!("{}_value", key), DataType::Utf8),
+            (format!("{}_probability", key), DataType::Float64),
+        ],
+    }
+}
+
+/// Cells of the columns returned by `field_columns` for the fused value of the field
+fn field_cells(key: &str, field_type: &FieldType, response: &SearchResponse, interval: f64) -> Vec<Cell> {
+    match (field_type, response.fields.get(key).map(|(_, value)| value)) {
+        (FieldType::Float { .. }, Some(FieldValue::Numeric(value))) => [
+            value.mu(),
+            value.sigma(),
+            value.quantile((1.0 - interval) / 2.0),
+            value.quantile((1.0 + interval) / 2.0),
+        ]
+        .into_iter()
+        // errors of conflicting reports have no moments
+        .map(|x| Cell::Number(Some(x).filter(|x| x.is_finite())))
+        .collect(),
+        (FieldType::Float { .. }, _) => vec![Cell::Number(None); 4],
+        (FieldType::String, Some(FieldValue::String(value))) => match value.most_probable() {
+            Some((value, probability)) => {
+                vec![Cell::Text(Some(value.clone())), Cell::Number(Some(probability))]
+            }
+            None => vec![Cell::Text(None), Cell::Number(None)],
+        },
+        (FieldType::String, _) => vec![Cell::Text(None), Cell::Number(None)],
+    }
+}
+
+/// Input columns followed by the columns of the fields. Fails if a name is used twice,
+/// as CSV and Parquet files would get ambiguous headers and JSONL objects would lose a value
+fn output_columns(
+    input_columns: &[String],
+    fields: &[(&String, &FieldType)],
+) -> Result<Vec<(String, DataType)>, EnrichError> {
+    let columns: Vec<(String, DataType)> = input_columns
+        .iter()
+        .map(|column| (column.clone(), DataType::Utf8))
+        .chain(fields.iter().flat_map(|(key, field_type)| field_columns(key, field_type)))
+        .collect();
+    let mut names = HashSet::new();
+    match columns.iter().find(|(name, _)| !names.insert(name)) {
+        Some((name, _)) => Err(EnrichError::Format(format!(
+            "column {} is written twice. Rename the input column or leave out its field with --fields",
+            name
+        ))),
+        None => Ok(columns),
+    }
+}
+
+/// Writes rows of cells to a file in one of the output formats
+enum RowWriter {
+    Csv(csv::Writer<File>),
+    Jsonl(BufWriter<File>),
+    Parquet {
+        writer: ArrowWriter<File>,
+        schema: Arc<Schema>,
+        rows: Vec<Vec<Cell>>,
+    },
+}
+
+impl RowWriter {
+    fn create(path: &Path, columns: &[(String, DataType)]) -> Result<Self, EnrichError> {
+        let format = Format::of(path)?;
+        let file = File::create(path)?;
+        Ok(match format {
+            Format::Csv => {
+                let mut writer = csv::Writer::from_writer(file);
+                writer.write_record(columns.iter().map(|(name, _)| name))?;
+                RowWriter::Csv(writer)
+            }
+            Format::Jsonl => RowWriter::Jsonl(BufWriter::new(file)),
+            Format::Parquet => {
+                let schema = Arc::new(Schema::new(
+                    columns
+                        .iter()
+                        .map(|(name, data_type)| ArrowField::new(name, data_type.clone(), true))
+                        .collect::<Vec<_>>(),
+                ));
+                RowWriter::Parquet {
+                    writer: ArrowWriter::try_new(file, schema.clone(), None)?,
+                    schema,
+                    rows: vec![],
+                }
+            }
+        })
+    }
+
+    fn write(&mut self, columns: &[(String, DataType)], row: Vec<Cell>) -> Result<(), EnrichError> {
+        match self {
+            RowWriter::Csv(writer) => {
+                writer.write_record(row.iter().map(|cell| match cell {
+                    Cell::Text(text) => text.clone().unwrap_or_default(),
+                    Cell::Number(number) => number.map(|n| n.to_string()).unwrap_or_default(),
+                }))?;
+            }
+            RowWriter::Jsonl(writer) => {
+                let object: serde_json::Map<String, serde_json::Value> = columns
+                    .iter()
+                    .zip(row)
+                    .map(|((name, _), cell)| {
+                        let value = match cell {
+                            Cell::Text(text) => text.into(),
+                            Cell::Number(number) => number.into(),
+                        };
+                        (name.clone(), value)
+                    })
+                    .collect();
+                serde_json::to_writer(&mut *writer, &object)?;
+                writeln!(writer)?;
+            }
+            RowWriter::Parquet { rows, .. } => {
+                rows.push(row);
+                if rows.len() >= PARQUET_ROW_GROUP {
+                    self.flush_parquet()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_parquet(&mut self) -> Result<(), EnrichError> {
+        if let RowWriter::Parquet { writer, schema, rows } = self {
+            if rows.is_empty() {
+                return Ok(());
+            }
+            let arrays: Vec<ArrayRef> = schema
+                .fields()
+                .iter()
+                .enumerate()
+                .map(|(i, field)| -> ArrayRef {
+                    match field.data_type() {
+                        DataType::Float64 => Arc::new(Float64Array::from_iter(rows.iter().map(|row| {
+                            match &row[i] {
+                                Cell::Number(number) => *number,
+                                Cell::Text(_) => None,
+                            }
+                        }))),
+                        _ => Arc::new(StringArray::from_iter(rows.iter().map(|row| match &row[i] {
+                            Cell::Text(text) => text.clone(),
+                            Cell::Number(number) => number.map(|n| n.to_string()),
+                        }))),
+                    }
+                })
+                .collect();
+            writer.write(&RecordBatch::try_new(schema.clone(), arrays)?)?;
+            rows.clear();
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), EnrichError> {
+        self.flush_parquet()?;
+        match self {
+            RowWriter::Csv(mut writer) => writer.flush()?,
+            RowWriter::Jsonl(mut writer) => writer.flush()?,
+            RowWriter::Parquet { writer, .. } => {
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Searches each row of the input file and writes its columns followed by the fused fields to the output file.
+/// Numeric fields are written as mean, standard deviation and the bounds of the central interval,
+/// string fields as the most probable value and its probability
+pub async fn enrich(engine: &SearchEngine, args: &EnrichArgs) -> Result<(), EnrichError> {
+    let (input_columns, rows) = read_input(&args.input)?;
+    let mut fields: Vec<(&String, &FieldType)> = match &args.fields {
+        Some(keys) => keys
+            .iter()
+            .map(|key| match engine.search_fields.get(key) {
+                Some(field) => Ok((key, &field.field_type)),
+                None => Err(EnrichError::Format(format!("unknown field {}", key))),
+            })
+            .collect::<Result<_, _>>()?,
+        None => engine
+            .search_fields
+            .iter()
+            .map(|(key, field)| (key, &field.field_type))
+            .collect(),
+    };
+    fields.sort_by_key(|(key, _)| *key);
+    let columns = output_columns(&input_columns, &fields)?;
+    let requested: Vec<RequestedField> = fields
+        .iter()
+        .map(|(key, _)| RequestedField::new(key.to_string()))
+        .collect();
+    let mut writer = RowWriter::create(&args.output, &columns)?;
+    let mut responses = stream::iter(&rows)
+        .map(|row| {
+            let mut hints = row.clone();
+            let query = hints.remove(&args.query_column).unwrap_or_default();
+            hints.retain(|_, value| !value.is_empty());
+            engine.search(SearchRequest {
+                query,
+                hints,
+                bypass_cache: args.no_cache,
+                fields: Some(requested.clone()),
+                ..Default::default()
+            })
+        })
+        .buffered(args.concurrency.max(1));
+    let mut unanswered = 0;
+    let mut done = 0;
+    while let Some(response) = responses.next().await {
+        let row = &rows[done];
+        if response.fields.is_empty() {
+            unanswered += 1;
+        }
+        let cells = input_columns
+            .iter()
+            .map(|column| Cell::Text(row.get(column).cloned()))
+            .chain(fields.iter().flat_map(|(key, field_type)| {
+                field_cells(key, field_type, &response, args.interval)
+            }))
+            .collect();
+        writer.write(&columns, cells)?;
+        done += 1;
+        if done % 1000 == 0 {
+            eprintln!("{} of {} queries searched", done, rows.len());
+        }
+    }
+    writer.finish()?;
+    eprintln!(
+        "Wrote {} rows to {}, {} queries without any field",
+        done,
+        args.output.display(),
+        unanswered
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+    use crate::{numeric::NumericFieldValue, search_engine::Field, string::StringFieldValue};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("enrich_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn read_csv_and_jsonl() {
+        let dir = temp_dir("read_input");
+        let csv_path = dir.join("input.csv");
+        std::fs::write(&csv_path, "query,barcode\napple,4011\nbanana,\n").unwrap();
+        let jsonl_path = dir.join("input.jsonl");
+        std::fs::write(
+            &jsonl_path,
+            "{\"query\": \"apple\", \"barcode\": 4011}\n\n{\"query\": \"banana\", \"brand\": null}\n",
+        )
+        .unwrap();
+        let csv = read_input(&csv_path).unwrap();
+        let jsonl = read_input(&jsonl_path).unwrap();
+        let parquet = read_input(&dir.join("input.parquet"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(csv.0, ["query", "barcode"]);
+        assert_eq!(csv.1[0]["barcode"], "4011");
+        assert_eq!(csv.1[1]["barcode"], "");
+        assert_eq!(jsonl.0, ["barcode", "query", "brand"]);
+        assert_eq!(jsonl.1.len(), 2, "empty line not skipped");
+        assert_eq!(jsonl.1[0]["barcode"], "4011", "number not kept as text");
+        assert!(!jsonl.1[1].contains_key("brand"), "null not left out");
+        assert!(matches!(parquet, Err(EnrichError::Format(_))));
+    }
+
+    #[test]
+    fn cells() {
+        let float = FieldType::Float { unit: "kg".to_string() };
+        let response = SearchResponse {
+            ids: HashMap::new(),
+            fields: HashMap::from([
+                (
+                    "weight".to_string(),
+                    (
+                        Field {
+                            description: "Weight".to_string(),
+                            field_type: float.clone(),
+                        },
+                        FieldValue::Numeric(NumericFieldValue::Normal { sigma: 0.5, mu: 1.0 }),
+                    ),
+                ),
+                (
+                    "color".to_string(),
+                    (
+                        Field {
+                            description: "Color".to_string(),
+                            field_type: FieldType::String,
+                        },
+                        FieldValue::String(StringFieldValue::Distribution(HashMap::from([
+                            ("yellow".to_string(), 0.7),
+                            ("green".to_string(), 0.3),
+                        ]))),
+                    ),
+                ),
+            ]),
+            provenance: HashMap::new(),
+            conflicts: vec![],
+            sources: vec![],
+        };
+        let numbers: Vec<f64> = field_cells("weight", &float, &response, 0.9)
+            .into_iter()
+            .map(|cell| match cell {
+                Cell::Number(Some(n)) => n,
+                other => panic!("unexpected cell {:?}", other),
+            })
+            .collect();
+        let expected = [1.0, 0.5, 1.0 - 1.645 * 0.5, 1.0 + 1.645 * 0.5];
+        assert!(
+            numbers.iter().zip(expected).all(|(n, e)| (n - e).abs() < 0.01),
+            "wrong numeric cells {:?}",
+            numbers
+        );
+        let color = field_cells("color", &FieldType::String, &response, 0.9);
+        assert!(matches!(&color[0], Cell::Text(Some(v)) if v == "yellow"));
+        assert!(matches!(color[1], Cell::Number(Some(p)) if (p - 0.7).abs() < 1e-9));
+        let missing = field_cells("size", &float, &response, 0.9);
+        assert!(missing.iter().all(|cell| matches!(cell, Cell::Number(None))));
+    }
+
+    #[test]
+    fn column_collision() {
+        let color = "color".to_string();
+        let fields = [(&color, &FieldType::String)];
+        let columns = output_columns(&["query".to_string(), "color".to_string()], &fields).unwrap();
+        let names: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["query", "color", "color_value", "color_probability"]);
+        assert!(output_columns(&["color_value".to_string()], &fields).is_err());
+    }
+
+    /// Writes two rows, the second with missing cells, to a file of the given extension
+    fn write_rows(dir: &Path, extension: &str) -> PathBuf {
+        let path = dir.join(format!("output.{}", extension));
+        let columns = [
+            ("query".to_string(), DataType::Utf8),
+            ("weight_mean".to_string(), DataType::Float64),
+        ];
+        let mut writer = RowWriter::create(&path, &columns).unwrap();
+        writer
+            .write(
+                &columns,
+                vec![Cell::Text(Some("apple".to_string())), Cell::Number(Some(0.2))],
+            )
+            .unwrap();
+        writer
+            .write(&columns, vec![Cell::Text(Some("banana".to_string())), Cell::Number(None)])
+            .unwrap();
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn write_each_format() {
+        let dir = temp_dir("row_writer");
+        let csv = std::fs::read_to_string(write_rows(&dir, "csv")).unwrap();
+        let jsonl = std::fs::read_to_string(write_rows(&dir, "jsonl")).unwrap();
+        let parquet_file = File::open(write_rows(&dir, "parquet")).unwrap();
+        let batches: Vec<RecordBatch> = ParquetRecordBatchReaderBuilder::try_new(parquet_file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let unknown = RowWriter::create(&dir.join("output.txt"), &[]);
+        let created = dir.join("output.txt").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(csv, "query,weight_mean\napple,0.2\nbanana,\n");
+        assert_eq!(
+            jsonl,
+            "{\"query\":\"apple\",\"weight_mean\":0.2}\n{\"query\":\"banana\",\"weight_mean\":null}\n"
+        );
+        assert_eq!(batches.len(), 1);
+        let means = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(means.value(0), 0.2);
+        assert!(means.is_null(1));
+        assert!(matches!(unknown, Err(EnrichError::Format(_))));
+        assert!(!created, "file of unknown format created");
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use crate::datasource::Candidate;
+
+/// Minimum normalized Levenshtein similarity of the labels of two candidates describing the same entity
+const LABEL_SIMILARITY_THRESHOLD: f64 = 0.8;
+
+/// Probability that a source which answered reports no candidate for an entity that matches the query
+const UNMATCHED_PROBABILITY: f64 = 0.05;
+
+/// Candidates of different data sources describing the same entity
+#[derive(Debug, Clone, Default)]
+pub struct Cluster {
+    /// Index of the data source and its candidate
+    pub members: Vec<(usize, Candidate)>,
+}
+
+impl Cluster {
+    /// Label of the best scoring member having one
+    pub fn label(&self) -> Option<&String> {
+        self.members.iter().find_map(|(_, c)| c.label.as_ref())
+    }
+
+    /// Identifiers of the members, keyed by scheme
+    pub fn ids(&self) -> HashMap<String, String> {
+        let mut ids = HashMap::new();
+        for (_, candidate) in &self.members {
+            for (scheme, id) in &candidate.result.ids {
+                ids.entry(scheme.clone()).or_insert_with(|| id.clone());
+            }
+        }
+        ids
+    }
+
+    fn accepts(&self, source: usize, candidate: &Candidate) -> bool {
+        if self.members.iter().any(|(s, _)| *s == source) {
+            return false;
+        }
+        let ids = self.ids();
+        if conflicting_scheme(&ids, &candidate.result.ids).is_some() {
+            return false;
+        }
+        if ids.iter().any(|(scheme, id)| candidate.result.ids.get(scheme) == Some(id)) {
+            return true;
+        }
+        match (self.label(), &candidate.label) {
+            (Some(a), Some(b)) => {
+                strsim::normalized_levenshtein(&a.to_lowercase(), &b.to_lowercase())
+                    >= LABEL_SIMILARITY_THRESHOLD
+            }
+            // candidates without label or identifier can describe any entity
+            _ => true,
+        }
+    }
+}
+
+/// First identifier scheme for which both sets of identifiers have different values
+pub fn conflicting_scheme<'a>(
+    a: &'a HashMap<String, String>,
+    b: &HashMap<String, String>,
+) -> Option<&'a String> {
+    a.iter()
+        .find(|(scheme, id)| b.get(*scheme).is_some_and(|other| other != *id))
+        .map(|(scheme, _)| scheme)
+}
+
+/// Sources with compatible identifiers, whose reports may be fused
+#[derive(Debug, Default)]
+pub struct Linkage {
+    /// Indices of the linked sources
+    pub linked: Vec<usize>,
+    /// Identifiers of the linked sources, keyed by scheme
+    pub ids: HashMap<String, String>,
+}
+
+/// Finds the largest set of sources whose identifiers do not contradict each other.
+/// Sources without identifiers are compatible with all others. On ties the set containing the first source wins
+pub fn link(ids: &[Option<&HashMap<String, String>>]) -> Linkage {
+    let mut groups: Vec<Linkage> = vec![];
+    for (i, source_ids) in ids.iter().enumerate() {
+        let source_ids = match source_ids {
+            Some(source_ids) if !source_ids.is_empty() => *source_ids,
+            _ => continue,
+        };
+        let group = match groups
+            .iter_mut()
+            .find(|g| conflicting_scheme(&g.ids, source_ids).is_none())
+        {
+            Some(group) => group,
+            None => {
+                groups.push(Linkage::default());
+                groups.last_mut().unwrap()
+            }
+        };
+        group.linked.push(i);
+        for (scheme, id) in source_ids {
+            group.ids.entry(scheme.clone()).or_insert_with(|| id.clone());
+        }
+    }
+    let mut best = Linkage::default();
+    for group in groups {
+        if group.linked.len() > best.linked.len() {
+            best = group;
+        }
+    }
+    // sources are only placed in the first group they fit, so others may also fit the chosen one
+    for (i, source_ids) in ids.iter().enumerate() {
+        let source_ids = match source_ids {
+            Some(source_ids) => *source_ids,
+            None => continue,
+        };
+        if best.linked.contains(&i) || conflicting_scheme(&best.ids, source_ids).is_some() {
+            continue;
+        }
+        best.linked.push(i);
+        for (scheme, id) in source_ids {
+            best.ids.entry(scheme.clone()).or_insert_with(|| id.clone());
+        }
+    }
+    best.linked.sort_unstable();
+    best
+}
+
+/// Groups the candidates of several sources sharing an identifier, or with similar labels if they share none.
+/// Candidates with contradicting identifiers are never grouped.
+/// Candidates are assigned greedily by decreasing score to the first cluster accepting them,
+/// so a candidate without label and identifier joins the best cluster its source is not yet part of
+pub fn cluster(mut candidates: Vec<(usize, Candidate)>) -> Vec<Cluster> {
+    candidates.sort_by(|(_, a), (_, b)| b.score.total_cmp(&a.score));
+    let mut clusters: Vec<Cluster> = vec![];
+    for (source, candidate) in candidates {
+        match clusters.iter_mut().find(|c| c.accepts(source, &candidate)) {
+            Some(cluster) => cluster.members.push((source, candidate)),
+            None => clusters.push(Cluster {
+                members: vec![(source, candidate)],
+            }),
+        }
+    }
+    clusters
+}
+
+/// Probability of each cluster being the entity searched for. Each source that answered
+/// is taken as independent evidence: its scores are normalized into a distribution over its candidates,
+/// and clusters it has no candidate in get a small constant probability
+pub fn match_probabilities(clusters: &[Cluster]) -> Vec<f64> {
+    let mut total_scores: HashMap<usize, f64> = HashMap::new();
+    for (source, candidate) in clusters.iter().flat_map(|c| &c.members) {
+        *total_scores.entry(*source).or_default() += candidate.score.max(0.0);
+    }
+    let likelihoods: Vec<f64> = clusters
+        .iter()
+        .map(|cluster| {
+            total_scores
+                .iter()
+                .map(|(source, total)| {
+                    match cluster.members.iter().find(|(s, _)| s == source) {
+                        Some((_, c)) if *total > 0.0 => c.score.max(0.0) / total,
+                        Some(_) => 1.0,
+                        None => UNMATCHED_PROBABILITY,
+                    }
+                })
+                .product()
+        })
+        .collect();
+    let total: f64 = likelihoods.iter().sum();
+    if total <= 0.0 {
+        return vec![1.0 / clusters.len() as f64; clusters.len()];
+    }
+    likelihoods.into_iter().map(|l| l / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_result::SearchResult;
+
+    fn candidate(label: Option<&str>, id: Option<&str>, score: f64) -> Candidate {
+        Candidate {
+            label: label.map(str::to_string),
+            score,
+            result: SearchResult {
+                ids: id
+                    .map(|id| HashMap::from([("gtin".to_string(), id.to_string())]))
+                    .unwrap_or_default(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn clusters() {
+        let clusters = cluster(vec![
+            (0, candidate(Some("Apple"), None, 1.0)),
+            (0, candidate(Some("Apple juice"), None, 0.5)),
+            (1, candidate(Some("apple"), None, 0.8)),
+            (1, candidate(Some("Apple juice"), Some("123"), 0.9)),
+            (2, candidate(None, None, 1.0)),
+        ]);
+        assert_eq!(clusters.len(), 2, "has wrong number of entities");
+        assert_eq!(clusters[0].label().unwrap(), "Apple");
+        assert_eq!(clusters[0].members.len(), 3, "anonymous candidate not in best cluster");
+        assert_eq!(clusters[1].members.len(), 2);
+        let probabilities = match_probabilities(&clusters);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(probabilities[0] > probabilities[1], "has wrong ranking");
+    }
+
+    #[test]
+    fn linkage() {
+        let ids = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        let a = ids(&[("gtin", "1")]);
+        let b = ids(&[("gtin", "2")]);
+        let c = ids(&[("gtin", "2"), ("internal_id", "x")]);
+        let none = HashMap::new();
+        let linkage = link(&[Some(&a), Some(&b), None, Some(&none), Some(&c)]);
+        assert_eq!(linkage.linked, vec![1, 3, 4]);
+        assert_eq!(linkage.ids, ids(&[("gtin", "2"), ("internal_id", "x")]));
+        let ean = ids(&[("ean", "5")]);
+        let linkage = link(&[Some(&b), Some(&a), Some(&a), Some(&a), Some(&ean)]);
+        assert_eq!(linkage.linked, vec![1, 2, 3, 4], "compatible source left out");
+        assert_eq!(linkage.ids, ids(&[("gtin", "1"), ("ean", "5")]));
+    }
+}
@@ -0,0 +1,214 @@
+pub mod datasource;
+pub mod datasources;
+pub mod entity_resolution;
+pub mod labelled_data;
+pub mod numeric;
+pub mod search_engine;
+pub mod search_engine_config;
+pub mod search_result;
+pub mod string;
+use std::{collections::BTreeMap, path::PathBuf};
+
+use clap::Parser;
+use futures::{stream, StreamExt};
+use labelled_data::{LabelledData, LabelledSample};
+use numeric::NumericFieldValue;
+use search_engine::{FieldValue, SearchResponse, VerifiedAnswer};
+use search_engine_config::Config;
+use string::StringFieldValue;
+
+/// Scores the fused results of the search engine against labelled ground truth,
+/// once with all data sources and once without each of them
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Path to the config file containing the fields and data sources to evaluate. Defaults to `config.yaml`
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Path to a file containing the labelled samples
+    #[arg(short, long)]
+    data: PathBuf,
+    /// Probability mass of the central intervals whose coverage is reported
+    #[arg(short, long, value_delimiter = ',', default_values_t = vec![0.5, 0.9])]
+    levels: Vec<f64>,
+    /// Number of queries evaluated concurrently
+    #[arg(long, default_value_t = 16)]
+    concurrency: usize,
+}
+
+/// Scores of the fused values of one field over all samples
+#[derive(Debug, Default)]
+struct FieldScores {
+    log_likelihood: Vec<f64>,
+    crps: Vec<f64>,
+    /// Whether the true value was inside the central interval, for each interval level
+    covered: Vec<Vec<bool>>,
+    brier: Vec<f64>,
+    /// Number of samples for which no valid value was fused
+    missing: usize,
+    /// Number of samples fused to an exact numeric value, left out of the log-likelihood
+    /// since densities of deltas are not comparable to those of continuous distributions
+    degenerate: usize,
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn score_numeric(value: &NumericFieldValue, truth: f64, levels: &[f64], scores: &mut FieldScores) {
+    if let NumericFieldValue::Error = value {
+        scores.missing += 1;
+        return;
+    }
+    match value {
+        NumericFieldValue::Exact(_) => scores.degenerate += 1,
+        _ => scores.log_likelihood.push(value.get_value(truth).ln()),
+    }
+    scores.crps.push(value.crps(truth));
+    scores.covered.resize(levels.len(), vec![]);
+    for (level, covered) in levels.iter().zip(scores.covered.iter_mut()) {
+        let lower = value.quantile((1.0 - level) / 2.0);
+        let upper = value.quantile((1.0 + level) / 2.0);
+        covered.push(truth >= lower && truth <= upper);
+    }
+}
+
+fn score_string(value: &StringFieldValue, truth: &str, scores: &mut FieldScores) {
+    let distribution = match value {
+        StringFieldValue::Exact(e) => BTreeMap::from([(e.clone(), 1.0)]),
+        StringFieldValue::Distribution(d) => d.clone().into_iter().collect(),
+        StringFieldValue::Error => {
+            scores.missing += 1;
+            return;
+        }
+    };
+    let probability = *distribution.get(truth).unwrap_or(&0.0);
+    scores.log_likelihood.push(probability.ln());
+    let brier = distribution
+        .iter()
+        .filter(|(k, _)| k.as_str() != truth)
+        .map(|(_, p)| p * p)
+        .sum::<f64>()
+        + (1.0 - probability).powi(2);
+    scores.brier.push(brier);
+}
+
+fn score(
+    responses: &[(&LabelledSample, SearchResponse)],
+    levels: &[f64],
+) -> BTreeMap<String, FieldScores> {
+    let mut scores: BTreeMap<String, FieldScores> = BTreeMap::new();
+    for (sample, response) in responses {
+        for (key, truth) in &sample.numeric_fields {
+            let field_scores = scores.entry(key.clone()).or_default();
+            match response.fields.get(key) {
+                Some((_, FieldValue::Numeric(value))) => {
+                    score_numeric(value, *truth, levels, field_scores)
+                }
+                _ => field_scores.missing += 1,
+            }
+        }
+        for (key, truth) in &sample.string_fields {
+            let field_scores = scores.entry(key.clone()).or_default();
+            match response.fields.get(key) {
+                Some((_, FieldValue::String(value))) => score_string(value, truth, field_scores),
+                _ => field_scores.missing += 1,
+            }
+        }
+    }
+    scores
+}
+
+fn format_score(value: Option<f64>, baseline: Option<f64>) -> String {
+    match (value, baseline) {
+        (Some(v), Some(b)) => format!("{:.4} ({:+.4})", v, v - b),
+        (Some(v), None) => format!("{:.4}", v),
+        (None, _) => "-".to_string(),
+    }
+}
+
+fn print_scores(
+    title: &str,
+    scores: &BTreeMap<String, FieldScores>,
+    baseline: Option<&BTreeMap<String, FieldScores>>,
+    levels: &[f64],
+) {
+    println!("{}", title);
+    for (key, field_scores) in scores {
+        let base = baseline.and_then(|b| b.get(key));
+        let mut line = format!(
+            "  {}: missing {}, exact {}, log-likelihood {}",
+            key,
+            field_scores.missing,
+            field_scores.degenerate,
+            format_score(
+                mean(&field_scores.log_likelihood),
+                base.and_then(|b| mean(&b.log_likelihood))
+            )
+        );
+        if !field_scores.crps.is_empty() {
+            line += &format!(
+                ", CRPS {}",
+                format_score(mean(&field_scores.crps), base.and_then(|b| mean(&b.crps)))
+            );
+        }
+        for (level, covered) in levels.iter().zip(&field_scores.covered) {
+            let coverage: Vec<f64> = covered.iter().map(|c| if *c { 1.0 } else { 0.0 }).collect();
+            line += &format!(
+                ", coverage of {}% interval {}",
+                level * 100.0,
+                format_score(mean(&coverage), None)
+            );
+        }
+        if !field_scores.brier.is_empty() {
+            line += &format!(
+                ", Brier score {}",
+                format_score(mean(&field_scores.brier), base.and_then(|b| mean(&b.brier)))
+            );
+        }
+        println!("{}", line);
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Cli::parse();
+    let config_path = args.config.unwrap_or_else(|| PathBuf::from("config.yaml"));
+    let configuration = Config::load(config_path).unwrap();
+    let data = LabelledData::load_from_file(args.data).unwrap();
+    let engine = &configuration.to_search_engine().await.unwrap();
+    let reports: Vec<(&LabelledSample, Vec<Option<VerifiedAnswer>>)> =
+        stream::iter(data.samples.iter())
+            .map(|sample| async move {
+                (sample, engine.collect_reports(sample.query.clone()).await)
+            })
+            .buffered(args.concurrency.max(1))
+            .collect()
+            .await;
+
+    let responses: Vec<_> = reports
+        .iter()
+        .map(|(sample, r)| (*sample, engine.fuse(r, false)))
+        .collect();
+    let all_scores = score(&responses, &args.levels);
+    print_scores("All data sources", &all_scores, None, &args.levels);
+    for (i, data_source) in engine.data_sources.iter().enumerate() {
+        let responses: Vec<_> = stream::iter(reports.iter())
+            .map(|(sample, r)| async move {
+                let ablated = engine.collect_reports_without(sample.query.clone(), r, i).await;
+                (*sample, engine.fuse(&ablated, false))
+            })
+            .buffered(args.concurrency.max(1))
+            .collect()
+            .await;
+        print_scores(
+            &format!("Without data source {}", data_source.name),
+            &score(&responses, &args.levels),
+            Some(&all_scores),
+            &args.levels,
+        );
+    }
+}
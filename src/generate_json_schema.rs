@@ -1,6 +1,12 @@
-use schemars::{schema_for};
+use schemars::{
+    gen::SchemaSettings,
+    schema::{RootSchema, Schema, SchemaObject},
+    visit::{visit_schema_object, Visitor},
+    JsonSchema,
+};
 pub mod datasource;
 pub mod datasources;
+pub mod entity_resolution;
 pub mod numeric;
 pub mod search_engine;
 pub mod search_engine_config;
@@ -8,8 +14,41 @@ pub mod search_result;
 pub mod string;
 use std::io::prelude::*;
 
+/// Lets the variants of an enum flattened into a struct, such as the type of a data source, appear next to
+/// the properties of the struct. schemars forbids any property besides the variant
+#[derive(Debug, Clone)]
+struct AllowFlattenedEnums;
+
+impl Visitor for AllowFlattenedEnums {
+    fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        visit_schema_object(self, schema);
+        if schema.object.as_ref().is_none_or(|o| o.properties.is_empty()) {
+            return;
+        }
+        let variants = schema.subschemas.as_mut().and_then(|s| s.one_of.as_mut());
+        for variant in variants.into_iter().flatten() {
+            if let Schema::Object(SchemaObject { object: Some(object), .. }) = variant {
+                object.additional_properties = None;
+            }
+        }
+    }
+}
+
+fn schema_for<T: JsonSchema>() -> RootSchema {
+    SchemaSettings::draft07()
+        .with_visitor(AllowFlattenedEnums)
+        .into_generator()
+        .into_root_schema_for::<T>()
+}
+
+fn write_schema(path: &str, schema: &RootSchema) {
+    let mut schema_file=std::fs::File::create(path).unwrap();
+    schema_file.write_all(serde_json::to_string_pretty(schema).unwrap().as_bytes()).unwrap();
+}
+
 pub fn main() {
-    let schema = schema_for!(search_engine::SearchResponse);
-    let mut schema_file=std::fs::File::create("schema.json").unwrap();
-    schema_file.write_all(serde_json::to_string_pretty(&schema).unwrap().as_bytes()).unwrap();
-}
\ No newline at end of file
+    write_schema("schema.json", &schema_for::<search_engine::SearchResponse>());
+    write_schema("config.schema.json", &schema_for::<search_engine_config::Config>());
+    write_schema("data_source_config.schema.json", &schema_for::<search_engine_config::DataSourceConfig>());
+    write_schema("mock.schema.json", &schema_for::<datasources::mock_datasource::MockDataSource>());
+}
@@ -1,21 +1,36 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use async_graphql::http::GraphiQLSource;
 // use async_graphql_poem::GraphQL;
 use poem::{handler, web::Html, IntoResponse};
 #[handler]
 pub async fn graphiql() -> impl IntoResponse {
-    Html(GraphiQLSource::build().endpoint("/").finish())
+    Html(
+        GraphiQLSource::build()
+            .endpoint("/")
+            .subscription_endpoint("/ws")
+            .finish(),
+    )
 }
 
 use crate::{
+    datasource::{DataSourceError, RequestedField, SearchRequest},
     numeric::NumericFieldValue,
-    search_engine::{SearchEngine, SearchResponse},
+    search_engine::{
+        DataSourceInfo, EntityCandidate, BATCH_CONCURRENCY, FieldProvenance, LinkageConflict, SearchEngine,
+        SearchResponse, SourceOutcome,
+    },
 };
-use async_graphql::{dynamic::*, Value};
+use async_graphql::{dynamic::*, SelectionField, Value};
+use futures::StreamExt;
 type KeyValuePair = (String, f64);
+/// Identifier scheme together with the identifier
+type Identifier = (String, String);
+/// Field key together with the contribution of a data source to it
+type KeyedFieldProvenance = (String, FieldProvenance);
 
-pub fn get_schema(search_engine: Arc<SearchEngine>) -> Result<Schema, SchemaError> {
+/// Builds the schema of an engine. `config_version` is returned by the `configVersion` query
+pub fn get_schema(search_engine: Arc<SearchEngine>, config_version: u64) -> Result<Schema, SchemaError> {
     let key_value_pair = Object::new("KeyValuePair")
         .field(Field::new(
             "key",
@@ -58,12 +73,12 @@ pub fn get_schema(search_engine: Arc<SearchEngine>) -> Result<Schema, SchemaErro
                                 };
                                 match &result.1 {
                                     crate::search_engine::FieldValue::String(_s) => {
-                                        return Ok(None)
+                                        Ok(None)
                                     }
                                     crate::search_engine::FieldValue::Numeric(n) => {
-                                        return Ok(Some(FieldValue::boxed_any(Box::new(n.clone()))))
+                                        Ok(Some(FieldValue::boxed_any(Box::new(n.clone()))))
                                     }
-                                };
+                                }
                             })
                         },
                     )
@@ -86,30 +101,30 @@ pub fn get_schema(search_engine: Arc<SearchEngine>) -> Result<Schema, SchemaErro
 
                                 match &result.1 {
                                     crate::search_engine::FieldValue::String(s) => {
-                                        return match s {
+                                        match s {
                                             crate::string::StringFieldValue::Exact(e) => {
-                                                return Ok(Some(FieldValue::list(vec![
+                                                Ok(Some(FieldValue::list(vec![
                                                     FieldValue::boxed_any(Box::new((
                                                         e.clone(),
                                                         1.0,
                                                     ))),
-                                                ])));
+                                                ])))
                                             }
                                             crate::string::StringFieldValue::Distribution(r) => {
-                                                return Ok(Some(FieldValue::list(r.iter().map(
+                                                Ok(Some(FieldValue::list(r.iter().map(
                                                     |(k, v)| {
                                                         FieldValue::boxed_any(Box::new((
                                                             k.clone(),
-                                                            v.clone(),
+                                                            *v,
                                                         )))
                                                     },
-                                                ))));
+                                                ))))
                                             }
                                             _ => Ok(None),
-                                        };
+                                        }
                                     }
-                                    _ => return Ok(None),
-                                };
+                                    _ => Ok(None),
+                                }
                             })
                         },
                     )
@@ -122,35 +137,565 @@ pub fn get_schema(search_engine: Arc<SearchEngine>) -> Result<Schema, SchemaErro
         }
     }
 
+    search_result_builder = search_result_builder.field(
+        Field::new(
+            "explain",
+            TypeRef::named_nn_list_nn("FieldProvenance"),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<SearchResponse>()?;
+                    let field = match ctx.args.get("field") {
+                        Some(f) => Some(f.string()?.to_owned()),
+                        None => None,
+                    };
+                    let provenance: Vec<KeyedFieldProvenance> = data
+                        .provenance
+                        .iter()
+                        .filter(|(key, _)| field.as_ref().map(|f| f == *key).unwrap_or(true))
+                        .flat_map(|(key, records)| {
+                            records.iter().map(move |r| (key.clone(), r.clone()))
+                        })
+                        .collect();
+                    Ok(Some(FieldValue::list(
+                        provenance.into_iter().map(|p| FieldValue::boxed_any(Box::new(p))),
+                    )))
+                })
+            },
+        )
+        .argument(
+            InputValue::new("field", TypeRef::named(TypeRef::STRING))
+                .description("Only explain this field"),
+        )
+        .description("Values reported by each data source and their influence on the fused fields"),
+    );
+
+    search_result_builder = search_result_builder.field(
+        Field::new(
+            "timed_out",
+            TypeRef::named_nn_list_nn(TypeRef::STRING),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<SearchResponse>()?;
+                    Ok(Some(Value::List(
+                        data.sources
+                            .iter()
+                            .filter(|o| o.error == Some(DataSourceError::Timeout))
+                            .map(|o| Value::from(o.source.clone()))
+                            .collect(),
+                    )))
+                })
+            },
+        )
+        .description("Names of the data sources that did not answer before their deadline"),
+    );
+
+    search_result_builder = search_result_builder.field(
+        Field::new(
+            "sources",
+            TypeRef::named_nn_list_nn("SourceOutcome"),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<SearchResponse>()?;
+                    Ok(Some(FieldValue::list(
+                        data.sources.iter().cloned().map(|o| FieldValue::boxed_any(Box::new(o))),
+                    )))
+                })
+            },
+        )
+        .description("Outcome of each data source that answered or failed so far"),
+    );
+
+    search_result_builder = search_result_builder.field(
+        Field::new(
+            "ids",
+            TypeRef::named_nn_list_nn("Identifier"),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<SearchResponse>()?;
+                    let mut ids: Vec<Identifier> =
+                        data.ids.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                    ids.sort();
+                    Ok(Some(FieldValue::list(
+                        ids.into_iter().map(|id| FieldValue::boxed_any(Box::new(id))),
+                    )))
+                })
+            },
+        )
+        .description("Identifiers of the entity reported by the fused data sources"),
+    );
+
+    search_result_builder = search_result_builder.field(
+        Field::new(
+            "conflicts",
+            TypeRef::named_nn_list_nn("LinkageConflict"),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<SearchResponse>()?;
+                    Ok(Some(FieldValue::list(
+                        data.conflicts.iter().map(|c| FieldValue::borrowed_any(c)),
+                    )))
+                })
+            },
+        )
+        .description("Answers left out of the fusion because they describe a different entity"),
+    );
+
     let root_builder = Object::new("Query").field(
         Field::new(
             "search",
             TypeRef::named(search_result_builder.type_name()),
             move |ctx| {
                 FieldFuture::new(async move {
-                    let query = ctx.args.try_get("query")?;
                     let search_engine = ctx.data::<Arc<SearchEngine>>()?;
-                    match query.string() {
-                        Ok(q) => {
-                            let cloned = q.to_owned();
-                            let result = search_engine.search(cloned.into()).await;
-                            Ok(Some(FieldValue::boxed_any(Box::new(result))))
-                        }
-                        Err(_) => Err(async_graphql::Error::new("")),
+                    let request = search_request(&ctx, Some(ctx.field()), search_engine)?;
+                    let result = search_engine.search(request).await;
+                    Ok(Some(FieldValue::boxed_any(Box::new(result))))
+                })
+            },
+        )
+        .argument(query_argument())
+        .argument(hints_argument())
+        .argument(no_cache_argument()),
+    )
+    .field(
+        Field::new(
+            "searchMany",
+            TypeRef::named_nn_list_nn(search_result_builder.type_name()),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let search_engine = ctx.data::<Arc<SearchEngine>>()?;
+                    let fields = requested_fields(Some(ctx.field()), search_engine);
+                    let explain = explains(Some(ctx.field()));
+                    let bypass_cache = bypass_cache(&ctx)?;
+                    let requests = ctx
+                        .args
+                        .try_get("queries")?
+                        .list()?
+                        .iter()
+                        .map(|query| {
+                            Ok(SearchRequest {
+                                query: query.string()?.to_owned(),
+                                bypass_cache,
+                                explain,
+                                fields: fields.clone(),
+                                ..Default::default()
+                            })
+                        })
+                        .collect::<Result<Vec<_>, async_graphql::Error>>()?;
+                    let results = search_engine.search_many(requests, BATCH_CONCURRENCY).await;
+                    Ok(Some(FieldValue::list(results.into_iter().map(FieldValue::owned_any))))
+                })
+            },
+        )
+        .argument(InputValue::new(
+            "queries",
+            TypeRef::named_nn_list_nn(TypeRef::STRING),
+        ))
+        .argument(no_cache_argument())
+        .description("Results of several queries in the same order, fetched in batches"),
+    )
+    .field(
+        Field::new(
+            "searchCandidates",
+            TypeRef::named_nn_list_nn("EntityCandidate"),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let search_engine = ctx.data::<Arc<SearchEngine>>()?;
+                    let result_selection = ctx.field().selection_set().find(|s| s.name() == "result");
+                    let request = search_request(&ctx, result_selection, search_engine)?;
+                    let mut candidates = search_engine.search_candidates(request).await;
+                    if let Some(limit) = ctx.args.get("limit").filter(|l| !l.is_null()) {
+                        candidates.truncate(limit.u64()? as usize);
                     }
+                    Ok(Some(FieldValue::list(
+                        candidates.into_iter().map(FieldValue::owned_any),
+                    )))
                 })
             },
         )
-        .argument(InputValue::new("query", TypeRef::named_nn(TypeRef::STRING))),
+        .argument(query_argument())
+        .argument(hints_argument())
+        .argument(no_cache_argument())
+        .argument(
+            InputValue::new("limit", TypeRef::named(TypeRef::INT))
+                .description("Maximum number of entities returned"),
+        )
+        .description("Entities matching the query, most probable first"),
+    )
+    .field(
+        Field::new(
+            "dataSources",
+            TypeRef::named_nn_list_nn("DataSource"),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let search_engine = ctx.data::<Arc<SearchEngine>>()?;
+                    let infos: Vec<DataSourceInfo> =
+                        search_engine.data_sources.iter().map(|ds| ds.info()).collect();
+                    Ok(Some(FieldValue::list(
+                        infos.into_iter().map(|info| FieldValue::boxed_any(Box::new(info))),
+                    )))
+                })
+            },
+        )
+        .description("Configured data sources and their status"),
+    )
+    .field(
+        Field::new("configVersion", TypeRef::named_nn(TypeRef::INT), move |_| {
+            FieldFuture::new(async move { Ok(Some(Value::from(config_version))) })
+        })
+        .description("Version of the config the server runs on, incremented each time it is reloaded"),
     );
-    let schema = Schema::build(root_builder.type_name(), None, None)
+    let subscription_builder = Subscription::new("Subscription").field(
+        SubscriptionField::new(
+            "search",
+            TypeRef::named_nn(search_result_builder.type_name()),
+            |ctx| {
+                SubscriptionFieldFuture::new(async move {
+                    let search_engine = ctx.data::<Arc<SearchEngine>>()?.clone();
+                    let request = search_request(&ctx, Some(ctx.field()), &search_engine)?;
+                    Ok(search_engine
+                        .search_progressively(request)
+                        .map(|result| Ok(FieldValue::owned_any(result))))
+                })
+            },
+        )
+        .argument(query_argument())
+        .argument(hints_argument())
+        .argument(no_cache_argument())
+        .description("Fused result of the data sources answered so far, updated each time a data source answers"),
+    );
+    let schema = Schema::build(
+        root_builder.type_name(),
+        None,
+        Some(subscription_builder.type_name()),
+    )
         .register(root_builder)
+        .register(subscription_builder)
         .register(search_result_builder)
         .register(gql_numeric_field_value)
         .register(key_value_pair)
+        .register(get_field_provenance())
+        .register(get_data_source())
+        .register(get_source_outcome())
+        .register(get_entity_candidate())
+        .register(get_identifier())
+        .register(get_linkage_conflict())
+        .register(
+            InputObject::new("QueryHint")
+                .description("Something known about the searched entity, e.g. its barcode")
+                .field(InputValue::new("key", TypeRef::named_nn(TypeRef::STRING)))
+                .field(InputValue::new("value", TypeRef::named_nn(TypeRef::STRING))),
+        )
+        .register(Scalar::new("JSON").description("Arbitrary JSON value"))
         .data(search_engine)
         .finish();
-    return schema;
+    schema
+}
+
+fn query_argument() -> InputValue {
+    InputValue::new("query", TypeRef::named(TypeRef::STRING))
+        .description("Free text describing the searched entity")
+}
+
+fn hints_argument() -> InputValue {
+    InputValue::new("hints", TypeRef::named_nn_list("QueryHint"))
+        .description("What else is known about the searched entity, e.g. its barcode, brand or category")
+}
+
+/// Builds the request from the arguments of a search and the fields selected in `result_selection`
+fn search_request(
+    ctx: &ResolverContext,
+    result_selection: Option<SelectionField>,
+    search_engine: &SearchEngine,
+) -> async_graphql::Result<SearchRequest> {
+    let query = match ctx.args.get("query").filter(|q| !q.is_null()) {
+        Some(q) => q.string()?.to_owned(),
+        None => String::new(),
+    };
+    let mut hints = BTreeMap::new();
+    if let Some(list) = ctx.args.get("hints").filter(|h| !h.is_null()) {
+        for hint in list.list()?.iter() {
+            let hint = hint.object()?;
+            hints.insert(
+                hint.try_get("key")?.string()?.to_owned(),
+                hint.try_get("value")?.string()?.to_owned(),
+            );
+        }
+    }
+    if query.is_empty() && hints.is_empty() {
+        return Err(async_graphql::Error::new("a query or hints are required"));
+    }
+    Ok(SearchRequest {
+        query,
+        hints,
+        bypass_cache: bypass_cache(ctx)?,
+        explain: explains(result_selection),
+        fields: requested_fields(result_selection, search_engine),
+        ..Default::default()
+    })
+}
+
+fn no_cache_argument() -> InputValue {
+    InputValue::new("no_cache", TypeRef::named(TypeRef::BOOLEAN))
+        .description("Query the data sources even if their results are cached")
+}
+
+fn bypass_cache(ctx: &ResolverContext) -> async_graphql::Result<bool> {
+    match ctx.args.get("no_cache") {
+        Some(value) => value.boolean(),
+        None => Ok(false),
+    }
+}
+
+/// Whether the provenance of the fused fields is selected, as computing it takes a fusion per data source
+fn explains(result_selection: Option<SelectionField>) -> bool {
+    result_selection.is_some_and(|selection| selection.selection_set().any(|s| s.name() == "explain"))
+}
+
+/// Search fields selected in the query, so that sources which cannot report any of them are not queried.
+/// All fields if none is selected or if the provenance is explained
+fn requested_fields(
+    result_selection: Option<SelectionField>,
+    search_engine: &SearchEngine,
+) -> Option<Vec<RequestedField>> {
+    let mut fields = vec![];
+    for selection in result_selection?.selection_set() {
+        if selection.name() == "explain" {
+            return None;
+        }
+        if search_engine.search_fields.contains_key(selection.name()) {
+            fields.push(RequestedField::new(selection.name().to_string()));
+        }
+    }
+    if fields.is_empty() {
+        return None;
+    }
+    Some(fields)
+}
+
+pub fn get_entity_candidate() -> Object {
+    Object::new("EntityCandidate")
+        .description("Entity matching a query, fused from the candidates of the data sources describing it")
+        .field(Field::new("label", TypeRef::named(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<EntityCandidate>()?;
+                Ok(data.label.clone().map(Value::from))
+            })
+        }))
+        .field(
+            Field::new("probability", TypeRef::named_nn(TypeRef::FLOAT), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<EntityCandidate>()?;
+                    Ok(Some(Value::from(data.probability)))
+                })
+            })
+            .description("Probability that this is the entity searched for"),
+        )
+        .field(Field::new("result", TypeRef::named_nn("SearchResult"), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<EntityCandidate>()?;
+                Ok(Some(FieldValue::borrowed_any(&data.response)))
+            })
+        }))
+}
+
+pub fn get_identifier() -> Object {
+    Object::new("Identifier")
+        .description("Identifier of an entity within a scheme, e.g. a GTIN")
+        .field(Field::new("scheme", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<Identifier>()?;
+                Ok(Some(Value::from(data.0.clone())))
+            })
+        }))
+        .field(Field::new("id", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<Identifier>()?;
+                Ok(Some(Value::from(data.1.clone())))
+            })
+        }))
+}
+
+pub fn get_linkage_conflict() -> Object {
+    Object::new("LinkageConflict")
+        .description("Answer left out of the fusion because its identifier contradicts those of the fused answers")
+        .field(Field::new("source", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<LinkageConflict>()?;
+                Ok(Some(Value::from(data.source.clone())))
+            })
+        }))
+        .field(Field::new("scheme", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<LinkageConflict>()?;
+                Ok(Some(Value::from(data.scheme.clone())))
+            })
+        }))
+        .field(
+            Field::new("id", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<LinkageConflict>()?;
+                    Ok(Some(Value::from(data.id.clone())))
+                })
+            })
+            .description("Identifier reported by the data source"),
+        )
+        .field(
+            Field::new("linked_id", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<LinkageConflict>()?;
+                    Ok(Some(Value::from(data.linked_id.clone())))
+                })
+            })
+            .description("Identifier of the fused answers"),
+        )
+}
+
+pub fn get_source_outcome() -> Object {
+    Object::new("SourceOutcome")
+        .description("Outcome of querying a data source")
+        .field(Field::new("source", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<SourceOutcome>()?;
+                Ok(Some(Value::from(data.source.clone())))
+            })
+        }))
+        .field(
+            Field::new("status", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<SourceOutcome>()?;
+                    Ok(Some(Value::from(match &data.error {
+                        Some(e) => e.name(),
+                        None => "Answered",
+                    })))
+                })
+            })
+            .description("Answered, or the kind of error: NotFound, Timeout, Transport, Decode, InvalidValue or Unavailable"),
+        )
+        .field(
+            Field::new("error", TypeRef::named(TypeRef::STRING), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<SourceOutcome>()?;
+                    Ok(data.error.as_ref().map(|e| Value::from(e.to_string())))
+                })
+            })
+            .description("Description of the error, missing if the data source answered"),
+        )
+}
+
+pub fn get_data_source() -> Object {
+    Object::new("DataSource")
+        .description("Configured data source")
+        .field(Field::new("name", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<DataSourceInfo>()?;
+                Ok(Some(Value::from(data.name.clone())))
+            })
+        }))
+        .field(Field::new("description", TypeRef::named(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<DataSourceInfo>()?;
+                Ok(data.description.clone().map(Value::from))
+            })
+        }))
+        .field(Field::new("tags", TypeRef::named_nn_list_nn(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<DataSourceInfo>()?;
+                Ok(Some(Value::List(data.tags.iter().cloned().map(Value::from).collect())))
+            })
+        }))
+        .field(
+            Field::new("status", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<DataSourceInfo>()?;
+                    Ok(Some(Value::from(data.status.name())))
+                })
+            })
+            .description("Whether the data source is available"),
+        )
+        .field(
+            Field::new("status_details", TypeRef::named(TypeRef::STRING), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<DataSourceInfo>()?;
+                    Ok(data.status.details().map(Value::from))
+                })
+            })
+            .description("Reason for the status, if the data source is not available"),
+        )
+        .field(
+            Field::new("cache_hits", TypeRef::named(TypeRef::INT), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<DataSourceInfo>()?;
+                    Ok(data.cache.as_ref().map(|c| Value::from(c.hits)))
+                })
+            })
+            .description("Number of queries answered from the cache, missing if the data source is not cached"),
+        )
+        .field(
+            Field::new("cache_misses", TypeRef::named(TypeRef::INT), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<DataSourceInfo>()?;
+                    Ok(data.cache.as_ref().map(|c| Value::from(c.misses)))
+                })
+            })
+            .description("Number of queries forwarded to the data source, missing if the data source is not cached"),
+        )
+        .field(
+            Field::new("circuit_breaker", TypeRef::named(TypeRef::STRING), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<DataSourceInfo>()?;
+                    Ok(data.circuit_breaker.map(|c| Value::from(c.name())))
+                })
+            })
+            .description("State of the circuit breaker (Closed, Open or HalfOpen), missing if the data source has none"),
+        )
+}
+
+pub fn get_field_provenance() -> Object {
+    Object::new("FieldProvenance")
+        .description("Contribution of a data source to a fused field")
+        .field(Field::new("field", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+            FieldFuture::new(async move {
+                let data = ctx.parent_value.try_downcast_ref::<KeyedFieldProvenance>()?;
+                Ok(Some(Value::from(data.0.clone())))
+            })
+        }))
+        .field(
+            Field::new("source", TypeRef::named_nn(TypeRef::STRING), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<KeyedFieldProvenance>()?;
+                    Ok(Some(Value::from(data.1.source.clone())))
+                })
+            })
+            .description("Name of the data source"),
+        )
+        .field(
+            Field::new("reported", TypeRef::named_nn("JSON"), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<KeyedFieldProvenance>()?;
+                    Ok(Some(Value::from_json(serde_json::to_value(&data.1.reported)?)?))
+                })
+            })
+            .description("Value as reported by the data source, before calibration"),
+        )
+        .field(
+            Field::new("dropped", TypeRef::named_nn(TypeRef::BOOLEAN), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<KeyedFieldProvenance>()?;
+                    Ok(Some(Value::from(data.1.dropped)))
+                })
+            })
+            .description("Whether the value was dropped because the field is unknown or of a different type"),
+        )
+        .field(
+            Field::new("influence", TypeRef::named(TypeRef::FLOAT), |ctx| {
+                FieldFuture::new(async move {
+                    let data = ctx.parent_value.try_downcast_ref::<KeyedFieldProvenance>()?;
+                    Ok(data.1.influence.map(Value::from))
+                })
+            })
+            .description("Total variation distance between the fused value with and without this source"),
+        )
 }
 
 struct NumericFieldGetter<'a, T> {
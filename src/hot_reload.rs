@@ -0,0 +1,197 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use async_graphql::{dynamic::Schema, Data, Executor, Request, Response};
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use crate::{
+    graphql::get_schema,
+    search_engine::SearchEngine,
+    search_engine_config::Config,
+    validate::{self, Problem},
+};
+
+/// Time between two checks of the watched files for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Search engine and GraphQL schema built from one version of the config
+pub struct Deployment {
+    /// Starts at 1 and is incremented by each successful reload
+    pub version: u64,
+    pub engine: Arc<SearchEngine>,
+    pub schema: Schema,
+}
+
+/// Serves the engine of the latest valid config. It is rebuilt when the config or one of its Mock files changes,
+/// while searches already running finish on the engine they started with
+#[derive(Clone)]
+pub struct LiveEngine {
+    config_paths: Vec<PathBuf>,
+    current: Arc<RwLock<Arc<Deployment>>>,
+}
+
+impl LiveEngine {
+    pub fn new(config_paths: Vec<PathBuf>, engine: SearchEngine) -> Result<Self, async_graphql::dynamic::SchemaError> {
+        let engine = Arc::new(engine);
+        let schema = get_schema(engine.clone(), 1)?;
+        Ok(LiveEngine {
+            config_paths,
+            current: Arc::new(RwLock::new(Arc::new(Deployment {
+                version: 1,
+                engine,
+                schema,
+            }))),
+        })
+    }
+
+    pub fn current(&self) -> Arc<Deployment> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Builds an engine from the config files and serves it from now on. If the config is invalid, the
+    /// current engine is kept and the problems are returned. Returns the new version otherwise
+    pub async fn reload(&self) -> Result<u64, Vec<Problem>> {
+        let paths = &self.config_paths;
+        let config = Config::load_layered(paths).map_err(|e| validate::locate(paths, vec![e]))?;
+        let errors = config.validate();
+        if !errors.is_empty() {
+            return Err(validate::locate(paths, errors));
+        }
+        let engine = Arc::new(
+            config
+                .to_search_engine()
+                .await
+                .map_err(|e| validate::locate(paths, vec![e]))?,
+        );
+        let version = self.current().version + 1;
+        let schema = get_schema(engine.clone(), version).map_err(|e| {
+            vec![Problem {
+                file: paths.last().cloned().unwrap_or_default(),
+                line: None,
+                message: e.to_string(),
+            }]
+        })?;
+        *self.current.write().unwrap() = Arc::new(Deployment {
+            version,
+            engine,
+            schema,
+        });
+        Ok(version)
+    }
+
+    /// Reloads the config whenever one of its files or of the files they reference is modified. Runs forever
+    pub async fn watch(self) {
+        let mut files = watched_files(&self.config_paths).unwrap_or_else(|| self.config_paths.clone());
+        let mut modified = modification_times(&files);
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+            if modification_times(&files) == modified {
+                continue;
+            }
+            // taken before reloading, so that files modified during the reload trigger another one.
+            // A config that fails to parse keeps the previous files watched
+            if let Some(new_files) = watched_files(&self.config_paths) {
+                files = new_files;
+            }
+            modified = modification_times(&files);
+            match self.reload().await {
+                Ok(version) => eprintln!("Reloaded config, now serving config version {}", version),
+                Err(problems) => {
+                    eprintln!("Invalid config, still serving config version {}:", self.current().version);
+                    for problem in problems {
+                        eprintln!("{}", problem);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The config files and the files they reference, if they can be read
+fn watched_files(config_paths: &[PathBuf]) -> Option<Vec<PathBuf>> {
+    let config = Config::load_layered(config_paths).ok()?;
+    Some(config_paths.iter().cloned().chain(config.referenced_files()).collect())
+}
+
+fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Executes GraphQL requests with the schema of the current engine
+#[async_trait]
+impl Executor for LiveEngine {
+    async fn execute(&self, request: Request) -> Response {
+        let deployment = self.current();
+        deployment.schema.execute(request).await
+    }
+
+    fn execute_stream(&self, request: Request, session_data: Option<Arc<Data>>) -> BoxStream<'static, Response> {
+        Executor::execute_stream(&self.current().schema, request, session_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_engine::FieldValue;
+
+    fn write_mock(path: &std::path::Path, weight: f64) {
+        let mock = format!(
+            "data:\n  banana:\n    numeric_fields:\n      weight:\n        Exact: {}\n    string_fields: {{}}\n",
+            weight
+        );
+        std::fs::write(path, mock).unwrap();
+    }
+
+    async fn banana_weight(live: &LiveEngine) -> f64 {
+        let response = live.current().engine.search("banana".to_string()).await;
+        match &response.fields["weight"].1 {
+            FieldValue::Numeric(value) => value.mu(),
+            FieldValue::String(_) => f64::NAN,
+        }
+    }
+
+    #[tokio::test]
+    async fn reload() {
+        let dir = std::env::temp_dir().join(format!("hot_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mock_path = dir.join("mock.yaml");
+        write_mock(&mock_path, 0.2);
+        let config_path = dir.join("config.yaml");
+        let config = format!(
+            "fields:\n  weight:\n    description: Weight\n    field_type:\n      Float:\n        unit: kg\n\
+            data_sources:\n  - name: mock\n    Mock:\n      data_path: {}\n",
+            mock_path.display()
+        );
+        std::fs::write(&config_path, &config).unwrap();
+        let paths = vec![config_path.clone()];
+        let engine = Config::load_layered(&paths).unwrap().to_search_engine().await.unwrap();
+        let live = LiveEngine::new(paths, engine).unwrap();
+        assert_eq!(banana_weight(&live).await, 0.2);
+
+        std::fs::write(&config_path, config.replace("unit: kg", "unit: [kg")).unwrap();
+        let invalid = live.reload().await;
+        let kept = live.current().version;
+        let kept_weight = banana_weight(&live).await;
+
+        std::fs::write(&config_path, &config).unwrap();
+        write_mock(&mock_path, 0.3);
+        let reloaded = live.reload().await;
+        let new_weight = banana_weight(&live).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(invalid.is_err(), "invalid config loaded");
+        assert_eq!(kept, 1, "version changed by an invalid config");
+        assert_eq!(kept_weight, 0.2, "engine of an invalid config served");
+        assert_eq!(reloaded.unwrap(), 2);
+        assert_eq!(new_weight, 0.3, "changed Mock file not picked up");
+    }
+}
@@ -0,0 +1,29 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Deserialize;
+
+/// A query together with the verified true values of the entity it describes
+#[derive(Debug, Clone, Deserialize)]
+pub struct LabelledSample {
+    pub query: String,
+    #[serde(default)]
+    pub numeric_fields: HashMap<String, f64>,
+    #[serde(default)]
+    pub string_fields: HashMap<String, String>,
+}
+
+/// Set of queries with known ground truth, used to calibrate and evaluate data sources
+#[derive(Debug, Clone, Deserialize)]
+pub struct LabelledData {
+    pub samples: Vec<LabelledSample>,
+}
+
+impl LabelledData {
+    /// Reads a labelled data file in any format supported by the `config` crate
+    pub fn load_from_file(data_file: impl Into<PathBuf>) -> Result<Self, config::ConfigError> {
+        config::Config::builder()
+            .add_source(config::File::from(data_file.into()))
+            .build()?
+            .try_deserialize()
+    }
+}
@@ -1,59 +1,120 @@
 pub mod datasource;
 pub mod datasources;
+pub mod enrich;
+pub mod entity_resolution;
 pub mod numeric;
+pub mod query;
 pub mod search_engine;
 pub mod search_engine_config;
 pub mod search_result;
 pub mod string;
-use std::{path::PathBuf, sync::Arc};
+pub mod validate;
+use std::{collections::BTreeMap, path::PathBuf};
 pub mod graphql;
-use async_graphql_poem::GraphQL;
+pub mod hot_reload;
+use async_graphql_poem::{GraphQL, GraphQLSubscription};
 use poem::EndpointExt;
 use poem::{
     get,
     listener::TcpListener,
+    post,
     middleware::AddData,
-    web::{Data, Path},
+    web::{Data, Path, Query},
     Route, Server,
 };
 
-use clap::{Parser};
-use graphql::get_schema;
-use search_engine_config::Config;
+use clap::{Parser, Subcommand};
+use hot_reload::LiveEngine;
+use search_engine_config::{Config, ConfigError};
 
 use crate::graphql::graphiql;
-use crate::search_engine::SearchEngine;
+use crate::datasource::{RequestedField, SearchRequest};
+use crate::search_engine::BATCH_CONCURRENCY;
 
 /// Data integration engine
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// Path to a config file containing the fields available on the search engine and the data sources. Defaults to `config.yaml`
+    /// Path to a config file containing the fields available on the search engine and the data sources. Defaults to `config.yaml`.
+    /// Can be repeated to merge several files in order, e.g. shared fields and the sources of an environment
     #[arg(short, long)]
-    config: Option<PathBuf>,
+    config: Vec<PathBuf>,
     /// Port to listen on. Defaults to 8080
     #[arg(short, long)]
     port: Option<u16>,
+    /// Runs the server if missing
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Searches the queries of a CSV or JSONL file and writes the fused fields to a CSV, JSONL or Parquet file
+    Enrich(enrich::EnrichArgs),
+    /// Searches one entity and prints the fused result
+    Query(query::QueryArgs),
+    /// Checks the config and the Mock files it references, and connects to the data sources
+    Validate,
 }
 
 #[tokio::main]
 async fn main() {
     let args = Cli::parse();
-    let fields_path = args.config.unwrap_or_else(|| PathBuf::from("config.yaml"));
-    let configuration: Config = config::Config::builder()
-        .add_source(config::File::from(fields_path))
-        .build()
-        .unwrap()
-        .try_deserialize()
-        .unwrap();
-    println!("{:?}", configuration);
-    let engine = configuration.to_search_engine().await;
-    let arced_engine = Arc::new(engine);
-    let schema = get_schema(arced_engine.clone()).unwrap();
+    let config_paths = match args.config {
+        paths if paths.is_empty() => vec![PathBuf::from("config.yaml")],
+        paths => paths,
+    };
+    if let Some(Command::Validate) = args.command {
+        let problems = validate::validate(&config_paths).await;
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        if !problems.is_empty() {
+            eprintln!("{} problems found", problems.len());
+            std::process::exit(1);
+        }
+        eprintln!("Config is valid");
+        return;
+    }
+    let configuration = match Config::load_layered(&config_paths) {
+        Ok(configuration) => configuration,
+        Err(e) => exit_invalid(&config_paths, vec![e]),
+    };
+    let errors = configuration.validate();
+    if !errors.is_empty() {
+        exit_invalid(&config_paths, errors);
+    }
+    if args.command.is_none() {
+        println!("{:?}", configuration);
+    }
+    let engine = match configuration.to_search_engine().await {
+        Ok(engine) => engine,
+        Err(e) => exit_invalid(&config_paths, vec![e]),
+    };
+    match &args.command {
+        Some(Command::Enrich(enrich_args)) => {
+            if let Err(e) = enrich::enrich(&engine, enrich_args).await {
+                eprintln!("Enrichment failed: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Query(query_args)) => {
+            query::query(&engine, query_args).await;
+            return;
+        }
+        Some(Command::Validate) | None => {}
+    }
+    let live = LiveEngine::new(config_paths, engine).unwrap();
+    tokio::spawn(live.clone().watch());
     let app = Route::new()
-        .at("/", get(graphiql).post(GraphQL::new(schema)))
+        .at("/", get(graphiql).post(GraphQL::new(live.clone())))
+        .at("/ws", get(GraphQLSubscription::new(live.clone())))
+        .at("/search", post(http_structured_search))
+        .at("/search_many", post(http_search_many))
         .at("/search/::query", http_search)
-        .with(AddData::new(arced_engine));
+        .at("/config/version", get(http_config_version))
+        .with(AddData::new(live));
     let port = args.port.unwrap_or(8080);
     println!("GraphiQL IDE: http://localhost:{}/", port);
     Server::new(TcpListener::bind(format!("0.0.0.0:{}", port)))
@@ -61,13 +122,123 @@ async fn main() {
         .await
         .unwrap();
 }
+
+/// Prints the problems of an invalid config and exits
+fn exit_invalid(config_paths: &[PathBuf], errors: Vec<ConfigError>) -> ! {
+    eprintln!("Invalid config:");
+    for problem in validate::locate(config_paths, errors) {
+        eprintln!("{}", problem);
+    }
+    std::process::exit(1);
+}
+
 use poem::web::Json;
 
+#[derive(serde::Deserialize)]
+struct SearchParams {
+    /// Query the data sources even if their results are cached
+    #[serde(default)]
+    no_cache: bool,
+    /// Record the contribution of each data source to the fused fields
+    #[serde(default)]
+    explain: bool,
+    /// Comma separated keys of the requested fields. Sources that cannot report any of them are not queried
+    fields: Option<String>,
+}
+
 #[poem::handler]
 async fn http_search(
-    search_engine: Data<&Arc<SearchEngine>>,
+    live: Data<&LiveEngine>,
     Path(query): Path<String>,
+    Query(params): Query<SearchParams>,
+) -> Json<serde_json::Value> {
+    let result = live
+        .current()
+        .engine
+        .search(SearchRequest {
+            query,
+            bypass_cache: params.no_cache,
+            explain: params.explain,
+            fields: params
+                .fields
+                .map(|f| f.split(',').map(|k| RequestedField::new(k.trim().to_string())).collect()),
+            ..Default::default()
+        })
+        .await;
+    Json(serde_json::to_value(result).unwrap())
+}
+
+/// Body of a structured search
+#[derive(serde::Deserialize)]
+struct StructuredSearch {
+    /// Free text describing the entity
+    #[serde(default)]
+    query: String,
+    /// What else is known about the entity, e.g. its `barcode`, `brand` or `category`
+    #[serde(default)]
+    hints: BTreeMap<String, String>,
+    /// Keys of the requested fields. All fields if missing
+    fields: Option<Vec<String>>,
+    #[serde(default)]
+    no_cache: bool,
+    #[serde(default)]
+    explain: bool,
+}
+
+impl From<StructuredSearch> for SearchRequest {
+    fn from(search: StructuredSearch) -> Self {
+        SearchRequest {
+            query: search.query,
+            hints: search.hints,
+            bypass_cache: search.no_cache,
+            explain: search.explain,
+            fields: search
+                .fields
+                .map(|f| f.into_iter().map(RequestedField::new).collect()),
+            ..Default::default()
+        }
+    }
+}
+
+#[poem::handler]
+async fn http_structured_search(
+    live: Data<&LiveEngine>,
+    Json(search): Json<StructuredSearch>,
 ) -> Json<serde_json::Value> {
-    let result = search_engine.search(query).await;
+    let result = live.current().engine.search(search).await;
     Json(serde_json::to_value(result).unwrap())
 }
+
+/// Item of a batch search, either a free text query or a structured search
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum BatchItem {
+    Query(String),
+    Structured(StructuredSearch),
+}
+
+impl From<BatchItem> for SearchRequest {
+    fn from(item: BatchItem) -> Self {
+        match item {
+            BatchItem::Query(query) => SearchRequest::from(query),
+            BatchItem::Structured(search) => search.into(),
+        }
+    }
+}
+
+/// Searches each item of a JSON array, returning the results in the same order
+#[poem::handler]
+async fn http_search_many(
+    live: Data<&LiveEngine>,
+    Json(items): Json<Vec<BatchItem>>,
+) -> Json<serde_json::Value> {
+    let requests = items.into_iter().map(SearchRequest::from).collect();
+    let results = live.current().engine.search_many(requests, BATCH_CONCURRENCY).await;
+    Json(serde_json::to_value(results).unwrap())
+}
+
+/// Version of the config the server runs on, incremented each time it is reloaded
+#[poem::handler]
+async fn http_config_version(live: Data<&LiveEngine>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "version": live.current().version }))
+}
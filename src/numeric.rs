@@ -1,6 +1,8 @@
-use std::f64::NAN;
-
-use peroxide::{fuga::Integral::*, numerical::integral::*};
+use peroxide::{
+    fuga::Integral::*,
+    numerical::integral::*,
+    special::function::{inv_erf, phi},
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -56,7 +58,7 @@ impl NumericFieldValue {
                 scaling_factor,
                 ..
             } => {
-                let result: f64 = components.into_iter().map(|val| val.get_value(x)).product();
+                let result: f64 = components.iter().map(|val| val.get_value(x)).product();
                 result * scaling_factor
             }
             NumericFieldValue::Error => f64::NAN,
@@ -69,8 +71,36 @@ impl NumericFieldValue {
             NumericFieldValue::Exact(v) => *v,
             NumericFieldValue::Uniform { min, max } => (min + max) / 2.0,
             NumericFieldValue::Combination { mu, .. } => *mu,
-            NumericFieldValue::Error => NAN,
+            NumericFieldValue::Error => f64::NAN,
+        }
+    }
+
+    /// Checks that the value describes a proper distribution. Returns the reason if it does not
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            NumericFieldValue::Normal { sigma, mu } => {
+                if !mu.is_finite() || !sigma.is_finite() || *sigma <= 0.0 {
+                    return Err(format!("normal distribution with mu {} and sigma {}", mu, sigma));
+                }
+            }
+            NumericFieldValue::Exact(v) => {
+                if !v.is_finite() {
+                    return Err(format!("exact value {}", v));
+                }
+            }
+            NumericFieldValue::Uniform { min, max } => {
+                if !min.is_finite() || !max.is_finite() || min >= max {
+                    return Err(format!("uniform distribution from {} to {}", min, max));
+                }
+            }
+            NumericFieldValue::Combination { components, .. } => {
+                for component in components {
+                    component.validate()?;
+                }
+            }
+            NumericFieldValue::Error => {}
         }
+        Ok(())
     }
 
     pub fn sigma(&self) -> f64 {
@@ -79,9 +109,156 @@ impl NumericFieldValue {
             NumericFieldValue::Exact(_) => 0.0,
             NumericFieldValue::Uniform { min, max } => (max - min) / 12.0_f64.sqrt(),
             NumericFieldValue::Combination { sigma, .. } => *sigma,
-            NumericFieldValue::Error => NAN,
+            NumericFieldValue::Error => f64::NAN,
+        }
+    }
+    /// Values of the cumulative distribution function on an evenly spaced grid between `lo` and `hi`,
+    /// obtained by integrating the density with the trapezoidal rule
+    fn cdf_grid(&self, lo: f64, hi: f64, steps: usize) -> Vec<(f64, f64)> {
+        let dx = (hi - lo) / steps as f64;
+        let mut grid = Vec::with_capacity(steps + 1);
+        let mut cdf = 0.0;
+        let mut previous_density = self.get_value(lo);
+        grid.push((lo, 0.0));
+        for i in 1..=steps {
+            let x = lo + i as f64 * dx;
+            let density = self.get_value(x);
+            cdf += (previous_density + density) / 2.0 * dx;
+            previous_density = density;
+            grid.push((x, cdf));
+        }
+        if cdf > 0.0 {
+            for point in grid.iter_mut() {
+                point.1 /= cdf;
+            }
+        }
+        grid
+    }
+
+    /// Range outside of which the distribution has (almost) no probability mass
+    fn support(&self) -> (f64, f64) {
+        match self {
+            NumericFieldValue::Uniform { min, max } => (*min, *max),
+            _ => (
+                self.mu() - 8.0 * self.sigma(),
+                self.mu() + 8.0 * self.sigma(),
+            ),
         }
     }
+
+    /// Probability that the value is at most `x`
+    pub fn cdf(&self, x: f64) -> f64 {
+        match self {
+            NumericFieldValue::Normal { sigma, mu } => phi((x - mu) / sigma),
+            NumericFieldValue::Exact(v) => {
+                if x >= *v {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            NumericFieldValue::Uniform { min, max } => ((x - min) / (max - min)).clamp(0.0, 1.0),
+            NumericFieldValue::Combination { .. } => {
+                let (lo, hi) = self.support();
+                if x <= lo {
+                    return 0.0;
+                }
+                if x >= hi {
+                    return 1.0;
+                }
+                self.cdf_grid(lo, hi, 1000)
+                    .into_iter()
+                    .take_while(|(grid_x, _)| *grid_x <= x)
+                    .last()
+                    .map(|(_, cdf)| cdf)
+                    .unwrap_or(0.0)
+            }
+            NumericFieldValue::Error => f64::NAN,
+        }
+    }
+
+    /// Smallest value `x` with a cumulative probability of at least `p`
+    pub fn quantile(&self, p: f64) -> f64 {
+        match self {
+            NumericFieldValue::Normal { sigma, mu } => {
+                mu + sigma * 2.0_f64.sqrt() * inv_erf(2.0 * p - 1.0)
+            }
+            NumericFieldValue::Exact(v) => *v,
+            NumericFieldValue::Uniform { min, max } => min + p * (max - min),
+            NumericFieldValue::Combination { .. } => {
+                let (lo, hi) = self.support();
+                let grid = self.cdf_grid(lo, hi, 1000);
+                let mut previous = grid[0];
+                for point in grid {
+                    if point.1 >= p {
+                        if point.1 == previous.1 {
+                            return point.0;
+                        }
+                        // interpolate linearly between the grid points
+                        return previous.0
+                            + (p - previous.1) / (point.1 - previous.1) * (point.0 - previous.0);
+                    }
+                    previous = point;
+                }
+                hi
+            }
+            NumericFieldValue::Error => f64::NAN,
+        }
+    }
+
+    /// Continuous ranked probability score of the distribution for the observed value `y`. Lower is better
+    pub fn crps(&self, y: f64) -> f64 {
+        match self {
+            NumericFieldValue::Normal { sigma, mu } => {
+                let z = (y - mu) / sigma;
+                let density = (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt();
+                sigma * (z * (2.0 * phi(z) - 1.0) + 2.0 * density - 1.0 / std::f64::consts::PI.sqrt())
+            }
+            NumericFieldValue::Exact(v) => (y - v).abs(),
+            NumericFieldValue::Uniform { .. } | NumericFieldValue::Combination { .. } => {
+                let (lo, hi) = self.support();
+                let (lo, hi) = (lo.min(y), hi.max(y));
+                let grid = self.cdf_grid(lo, hi, 2000);
+                let dx = (hi - lo) / 2000.0;
+                grid.iter()
+                    .map(|(x, cdf)| {
+                        let observed = if *x >= y { 1.0 } else { 0.0 };
+                        (cdf - observed).powi(2) * dx
+                    })
+                    .sum()
+            }
+            NumericFieldValue::Error => f64::NAN,
+        }
+    }
+
+    /// Total variation distance to another distribution, between 0 (identical) and 1 (disjoint).
+    /// Missing if either is an error
+    pub fn total_variation_distance(&self, other: &Self) -> Option<f64> {
+        Some(match (self, other) {
+            (NumericFieldValue::Error, _) | (_, NumericFieldValue::Error) => return None,
+            (NumericFieldValue::Exact(a), NumericFieldValue::Exact(b)) => {
+                if a == b {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            (NumericFieldValue::Exact(_), _) | (_, NumericFieldValue::Exact(_)) => 1.0,
+            _ => {
+                let (lo_1, hi_1) = self.support();
+                let (lo_2, hi_2) = other.support();
+                let (lo, hi) = (lo_1.min(lo_2), hi_1.max(hi_2));
+                let steps = 2000;
+                let dx = (hi - lo) / steps as f64;
+                let distance: f64 = (0..steps)
+                    .map(|i| lo + (i as f64 + 0.5) * dx)
+                    .map(|x| (self.get_value(x) - other.get_value(x)).abs() * dx)
+                    .sum();
+                (distance / 2.0).clamp(0.0, 1.0)
+            }
+        })
+    }
+
     /// takes a callback that maps x and the probability density at x to the value to be integrated
     pub fn integrate<F>(&self, f: F) -> f64
     where
@@ -94,12 +271,56 @@ impl NumericFieldValue {
         integrate(|x1| f(x1, self.get_value(x1)), range, G20K41(1.0e-3))
     }
 
+    /// Raises the likelihood to the power of `weight`, renormalized to a probability distribution.
+    /// Weights below 1 make the distribution less informative, weights above 1 more informative.
+    /// Deltas and uniform distributions are indicators, which stay the same under any power:
+    /// a delta stays a delta whatever the weight of its source
+    pub fn temper(&self, weight: f64) -> Self {
+        match self {
+            NumericFieldValue::Normal { sigma, mu } => NumericFieldValue::Normal {
+                sigma: sigma / weight.sqrt(),
+                mu: *mu,
+            },
+            // the product of powers is the power of the product
+            NumericFieldValue::Combination { components, .. } => NumericFieldValue::merge(
+                components.iter().map(|c| c.temper(weight)).collect(),
+            ),
+            NumericFieldValue::Exact(_)
+            | NumericFieldValue::Uniform { .. }
+            | NumericFieldValue::Error => self.clone(),
+        }
+    }
+
+    /// Removes a known bias from the reported value and scales its spread by `sigma_inflation`.
+    /// Combinations are recalibrated component-wise.
+    pub fn recalibrate(&self, bias: f64, sigma_inflation: f64) -> Self {
+        match self {
+            NumericFieldValue::Normal { sigma, mu } => NumericFieldValue::Normal {
+                sigma: sigma * sigma_inflation,
+                mu: mu - bias,
+            },
+            NumericFieldValue::Exact(v) => NumericFieldValue::Exact(v - bias),
+            NumericFieldValue::Uniform { min, max } => {
+                let center = (min + max) / 2.0 - bias;
+                let half_width = (max - min) / 2.0 * sigma_inflation;
+                NumericFieldValue::Uniform {
+                    min: center - half_width,
+                    max: center + half_width,
+                }
+            }
+            NumericFieldValue::Combination { components, .. } => NumericFieldValue::merge(
+                components
+                    .iter()
+                    .map(|c| c.recalibrate(bias, sigma_inflation))
+                    .collect(),
+            ),
+            NumericFieldValue::Error => NumericFieldValue::Error,
+        }
+    }
+
     pub fn merge(v: Vec<Self>) -> Self {
         // propagate errors
-        if v.iter().any(|val| match val {
-            NumericFieldValue::Error => true,
-            _ => false,
-        }) {
+        if v.iter().any(|val| matches!(val, NumericFieldValue::Error)) {
             return NumericFieldValue::Error;
         }
         let mut exacts = v.iter().filter_map(|val| match val {
@@ -136,12 +357,12 @@ impl NumericFieldValue {
             range,
             G20K41(1.0e-3),
         );
-        return NumericFieldValue::Combination {
+        NumericFieldValue::Combination {
             components: v,
             scaling_factor: 1.0 / area,
-            mu: mu,
+            mu,
             sigma: variance.sqrt(),
-        };
+        }
     }
 
     pub fn get_distribution(&self, steps: usize) -> DistributionPlot {
@@ -197,6 +418,61 @@ mod tests {
         );
     }
     #[test]
+    fn tempered() {
+        let tempered = NumericFieldValue::Normal {
+            sigma: 0.4,
+            mu: 0.2,
+        }
+        .temper(0.25);
+        assert!((tempered.sigma() - 0.8).abs() < 1e-9, "has wrong sigma");
+        let combination = NumericFieldValue::merge(vec![
+            NumericFieldValue::Normal { sigma: 1.0, mu: 0.0 },
+            NumericFieldValue::Normal { sigma: 1.0, mu: 2.0 },
+        ])
+        .temper(0.5);
+        assert!((combination.mu() - 1.0).abs() < 0.01, "has wrong mu");
+        assert!(
+            (combination.sigma() - 1.0).abs() < 0.01,
+            "has wrong sigma"
+        );
+        let uniform = NumericFieldValue::Uniform { min: 1.0, max: 3.0 }.temper(0.25);
+        assert!(
+            matches!(uniform, NumericFieldValue::Uniform { min, max } if min == 1.0 && max == 3.0),
+            "indicator changed by a power"
+        );
+        assert!(
+            matches!(NumericFieldValue::Exact(2.0).temper(0.75), NumericFieldValue::Exact(v) if v == 2.0),
+            "delta changed by a power"
+        );
+    }
+    #[test]
+    fn scores() {
+        let normal = NumericFieldValue::Normal {
+            sigma: 2.0,
+            mu: 1.0,
+        };
+        assert!((normal.cdf(1.0) - 0.5).abs() < 1e-9, "has wrong cdf");
+        assert!(
+            (normal.quantile(0.975) - (1.0 + 1.96 * 2.0)).abs() < 0.01,
+            "has wrong quantile"
+        );
+        let uniform = NumericFieldValue::Uniform { min: 0.0, max: 1.0 };
+        // CRPS of U(0, 1) at y is y^2 - y + 1/3
+        assert!(
+            (uniform.crps(0.25) - (0.0625 - 0.25 + 1.0 / 3.0)).abs() < 1e-3,
+            "has wrong crps"
+        );
+        let combination = NumericFieldValue::merge(vec![
+            NumericFieldValue::Uniform { min: -1.0, max: 1.0 },
+            NumericFieldValue::Uniform { min: 0.0, max: 2.0 },
+        ]);
+        assert!(
+            (combination.quantile(0.9) - 0.9).abs() < 0.01,
+            "has wrong quantile"
+        );
+        assert!((combination.cdf(0.5) - 0.5).abs() < 0.01, "has wrong cdf");
+    }
+    #[test]
     fn combination() {
         let uf = NumericFieldValue::merge(vec![
             NumericFieldValue::Uniform {
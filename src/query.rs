@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    datasource::{RequestedField, SearchRequest},
+    numeric::NumericFieldValue,
+    search_engine::{FieldType, FieldValue, SearchEngine, SearchResponse},
+    string::StringFieldValue,
+};
+
+/// Probability covered by the interval printed for numeric fields
+const INTERVAL: f64 = 0.9;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum OutputFormat {
+    /// Human readable table of the fused fields and the outcome of each data source
+    Table,
+    /// Full search response, including the provenance of each field
+    Json,
+}
+
+/// Searches one entity and prints the fused result
+#[derive(clap::Args, Debug)]
+pub struct QueryArgs {
+    /// Free text describing the entity
+    pub text: String,
+    /// What else is known about the entity as key=value, e.g. `barcode=4011`. Can be repeated
+    #[arg(long = "hint", value_parser = parse_hint)]
+    pub hints: Vec<(String, String)>,
+    /// Comma separated keys of the fields to search. Defaults to all fields
+    #[arg(long, value_delimiter = ',')]
+    pub fields: Option<Vec<String>>,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+    /// Query the data sources even if their results are cached
+    #[arg(long)]
+    pub no_cache: bool,
+}
+
+fn parse_hint(hint: &str) -> Result<(String, String), String> {
+    hint.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected key=value, got {}", hint))
+}
+
+pub async fn query(engine: &SearchEngine, args: &QueryArgs) {
+    let response = engine
+        .search(SearchRequest {
+            query: args.text.clone(),
+            hints: args.hints.iter().cloned().collect(),
+            bypass_cache: args.no_cache,
+            explain: matches!(args.format, OutputFormat::Json),
+            fields: args
+                .fields
+                .as_ref()
+                .map(|f| f.iter().cloned().map(RequestedField::new).collect()),
+            ..Default::default()
+        })
+        .await;
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&response).unwrap()),
+        OutputFormat::Table => print!("{}", table(&response)),
+    }
+}
+
+/// Fused fields, identifiers and source outcomes as aligned columns
+fn table(response: &SearchResponse) -> String {
+    let fields: BTreeMap<String, String> = response
+        .fields
+        .iter()
+        .map(|(key, (field, value))| {
+            let name = match &field.field_type {
+                FieldType::Float { unit } => format!("{} [{}]", key, unit),
+                FieldType::String => key.clone(),
+            };
+            (name, describe(value))
+        })
+        .collect();
+    let mut sections = vec![columns("FIELD", "VALUE", fields)];
+    if !response.ids.is_empty() {
+        sections.push(columns("SCHEME", "ID", response.ids.clone().into_iter().collect()));
+    }
+    let sources = response
+        .sources
+        .iter()
+        .map(|outcome| {
+            let status = match &outcome.error {
+                Some(e) => e.to_string(),
+                None => "answered".to_string(),
+            };
+            (outcome.source.clone(), status)
+        })
+        .chain(response.conflicts.iter().map(|c| {
+            let status = format!("left out, {} {} differs from {}", c.scheme, c.id, c.linked_id);
+            (c.source.clone(), status)
+        }))
+        .collect();
+    sections.push(columns("SOURCE", "STATUS", sources));
+    sections.join("\n")
+}
+
+fn describe(value: &FieldValue) -> String {
+    match value {
+        FieldValue::Numeric(NumericFieldValue::Error) | FieldValue::String(StringFieldValue::Error) => {
+            "conflicting reports".to_string()
+        }
+        FieldValue::Numeric(value) => format!(
+            "{:.4} ± {:.4}  ({:.0}%: {:.4} to {:.4})",
+            value.mu(),
+            value.sigma(),
+            INTERVAL * 100.0,
+            value.quantile((1.0 - INTERVAL) / 2.0),
+            value.quantile((1.0 + INTERVAL) / 2.0)
+        ),
+        FieldValue::String(value) => match value.most_probable() {
+            Some((value, probability)) => format!("{}  (p = {:.2})", value, probability),
+            None => "no value".to_string(),
+        },
+    }
+}
+
+fn columns(left: &str, right: &str, rows: BTreeMap<String, String>) -> String {
+    let width = rows.keys().map(|k| k.chars().count()).chain([left.len()]).max().unwrap_or(0);
+    let mut text = format!("{:width$}  {}\n", left, right);
+    for (key, value) in rows {
+        text += &format!("{:width$}  {}\n", key, value);
+    }
+    text
+}
@@ -1,11 +1,25 @@
-use futures::future::join_all;
+use futures::{
+    future::{join_all, BoxFuture},
+    stream::{self, FuturesUnordered},
+    Future, Stream, StreamExt,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::{timeout_at, Instant};
 
 use crate::{
-    datasource::DataSource, numeric::NumericFieldValue, search_result::SearchResult,
-    string::StringFieldValue,
+    datasource::{DataSource, DataSourceError, DataSourceStatus, RequestedField, SearchRequest},
+    datasources::{cached_datasource::CacheStatistics, resilient_datasource::CircuitState},
+    entity_resolution,
+    numeric::NumericFieldValue,
+    search_engine_config::NumericCalibration,
+    search_result::{SearchResult, SourceReport},
+    string::{ConfusionMatrix, StringFieldValue},
 };
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize,JsonSchema)]
@@ -27,59 +41,626 @@ pub enum FieldValue {
 }
 
 pub type SearchFields=HashMap<String, Field>;
+
+/// Number of requests sent to a data source at once by `SearchEngine::search_many`
+pub const BATCH_SIZE: usize = 100;
+
+/// Number of batches searched at the same time by the batch endpoints
+pub const BATCH_CONCURRENCY: usize = 4;
+
+/// A data source together with the configuration describing how to interpret its reports
+pub struct ConfiguredDataSource {
+    /// Unique name of the source, used in logs, errors and provenance records
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub data_source: Box<dyn DataSource + Sync + Send>,
+    /// Confusion matrices `P(reported | true)` of string fields, keyed by field name
+    pub confusion_matrices: HashMap<String, ConfusionMatrix>,
+    /// Exponent applied to the likelihoods reported by the source during fusion
+    pub weight: f64,
+    /// Corrections of the systematic errors of numeric fields, keyed by field name
+    pub calibration: HashMap<String, NumericCalibration>,
+    /// Group of sources sharing evidence with this one
+    pub correlation_group: Option<String>,
+    /// Time after which the answer of the source is no longer waited for
+    pub timeout: Option<Duration>,
+    /// Keys of the fields the source can report. All fields if missing
+    pub provides: Option<HashSet<String>>,
+    /// Keys of the fused fields passed to the source. It is queried after the sources that may provide them
+    pub consumes: Vec<String>,
+}
+
+/// Description of a configured data source and its current status
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DataSourceInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub status: DataSourceStatus,
+    /// Missing if the data source is not cached
+    pub cache: Option<CacheStatistics>,
+    /// Missing if the data source has no circuit breaker
+    pub circuit_breaker: Option<CircuitState>,
+}
+
+impl ConfiguredDataSource {
+    pub fn info(&self) -> DataSourceInfo {
+        DataSourceInfo {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            tags: self.tags.clone(),
+            status: self.data_source.status(),
+            cache: self.data_source.cache_statistics(),
+            circuit_breaker: self.data_source.circuit_state(),
+        }
+    }
+
+    /// Whether the source may report any of the requested fields
+    pub fn can_contribute(&self, request: &SearchRequest) -> bool {
+        match &self.provides {
+            Some(provides) => provides.iter().any(|key| request.requests(key)),
+            None => true,
+        }
+    }
+
+    /// Whether the source may report the field with the given key
+    pub fn may_provide(&self, key: &str) -> bool {
+        match &self.provides {
+            Some(provides) => provides.contains(key),
+            None => true,
+        }
+    }
+
+    /// Request passed to the source, carrying the fused values of the fields it consumes
+    fn request_with_context(&self, request: &SearchRequest, fused: Option<&SearchResponse>) -> SearchRequest {
+        let mut request = request.clone();
+        for key in &self.consumes {
+            match fused.and_then(|f| f.fields.get(key)) {
+                Some((_, FieldValue::Numeric(NumericFieldValue::Error)))
+                | Some((_, FieldValue::String(StringFieldValue::Error)))
+                | None => {}
+                Some((_, FieldValue::Numeric(value))) => {
+                    request.context.numeric_fields.insert(key.clone(), value.clone());
+                }
+                Some((_, FieldValue::String(value))) => {
+                    request.context.string_fields.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        request
+    }
+
+    /// Converts the values reported by the source into likelihoods of the true values
+    pub fn calibrate(&self, mut result: SearchResult) -> SearchResult {
+        for (key, value) in result.numeric_fields.iter_mut() {
+            if let Some(calibration) = self.calibration.get(key) {
+                *value = value.recalibrate(calibration.bias, calibration.sigma_inflation);
+            }
+        }
+        for (key, value) in result.string_fields.iter_mut() {
+            if let Some(confusion_matrix) = self.confusion_matrices.get(key) {
+                *value = value.to_likelihood(confusion_matrix);
+            }
+        }
+        result
+    }
+}
+
 //#[derive(Clone)]
 pub struct SearchEngine {
     pub search_fields: SearchFields,
-    pub data_sources: Vec<Box<dyn DataSource+Sync+Send>>,
+    pub data_sources: Vec<ConfiguredDataSource>,
+    /// Time after which a search returns with the answers received so far
+    pub search_timeout: Option<Duration>,
+    /// Indices of the data sources, grouped into stages queried one after the other
+    pub stages: Vec<Vec<usize>>,
+}
+
+/// Data sources consuming fields that they may provide to each other
+#[derive(Debug, Clone)]
+pub struct DependencyCycle {
+    /// Names of the data sources, each consuming a field the next one may provide
+    pub sources: Vec<String>,
+}
+
+impl std::fmt::Display for DependencyCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "data sources consume fields they may provide to each other: {}. \
+            Set `provides` to the fields each source can report",
+            self.sources.join(" -> ")
+        )
+    }
+}
+
+impl std::error::Error for DependencyCycle {}
+
+/// Answer of a data source to a query
+pub type SourceAnswer = Result<SearchResult, DataSourceError>;
+
+/// Result of a data source split by `SearchEngine::verify` into the fields that are fused and those that are not
+#[derive(Debug, Clone)]
+pub struct VerifiedResult {
+    /// Fields configured with the type of the reported value
+    pub result: SearchResult,
+    /// Fields that are unknown or of a different type, kept for provenance
+    pub dropped: SearchResult,
+}
+
+/// Answer of a data source after verification
+pub type VerifiedAnswer = Result<VerifiedResult, DataSourceError>;
+
+/// Outcome of querying one data source
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SourceOutcome {
+    /// Name of the data source
+    pub source: String,
+    /// Reason why the data source returned no result. Missing if it answered
+    pub error: Option<DataSourceError>,
+}
+
+/// Entity matching a query, fused from the candidates of the data sources describing it
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct EntityCandidate {
+    pub label: Option<String>,
+    /// Probability that this is the entity searched for
+    pub probability: f64,
+    pub response: SearchResponse,
+}
+
+/// Contribution of one data source to a fused field
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FieldProvenance {
+    /// Name of the data source
+    pub source: String,
+    /// Value as reported by the data source, before calibration
+    pub reported: FieldValue,
+    /// Whether the value was dropped because the field is unknown or of a different type
+    pub dropped: bool,
+    /// Total variation distance between the fused value with and without this source:
+    /// 0 if the source did not change the result, 1 if it determined it completely.
+    /// Missing if the value was dropped or if the fused value is an error
+    pub influence: Option<f64>,
+}
+
+/// Answer left out of the fusion because its identifier contradicts those of the fused answers
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LinkageConflict {
+    /// Name of the data source
+    pub source: String,
+    /// Identifier scheme with different values, e.g. `gtin`
+    pub scheme: String,
+    /// Identifier reported by the data source
+    pub id: String,
+    /// Identifier of the fused answers
+    pub linked_id: String,
 }
 
 #[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct SearchResponse {
+    /// Identifiers of the entity, keyed by scheme
+    pub ids: HashMap<String, String>,
     /// Map from the field key to its value
     pub fields: HashMap<String, (Field, FieldValue)>,
+    /// Map from the field key to the values reported for it by the data sources.
+    /// Empty unless the request asks to explain the fused fields
+    pub provenance: HashMap<String, Vec<FieldProvenance>>,
+    /// Answers left out of the fusion because they describe a different entity
+    pub conflicts: Vec<LinkageConflict>,
+    /// Outcome of each data source that answered or failed so far
+    pub sources: Vec<SourceOutcome>,
 }
 
 impl SearchEngine {
-    pub fn verify(&self, result: &SearchResult) -> SearchResult {
-        // Check that all fields in result are known and of correct type
-        let mut cloned_result = result.clone();
-        for (key, _) in &result.numeric_fields {
-            if let Some(field) = self.search_fields.get(key) {
-                match field.field_type {
-                    FieldType::Float { .. } => {}
-                    _ => {
-                        cloned_result.numeric_fields.remove(key);
+    pub fn new(
+        search_fields: SearchFields,
+        data_sources: Vec<ConfiguredDataSource>,
+        search_timeout: Option<Duration>,
+    ) -> Result<Self, DependencyCycle> {
+        let stages = Self::stages(&data_sources)?;
+        Ok(SearchEngine {
+            search_fields,
+            data_sources,
+            search_timeout,
+            stages,
+        })
+    }
+
+    /// Orders the data sources so that each is queried after all other sources that may provide a field it consumes.
+    /// Sources in the same stage do not depend on each other and are queried concurrently
+    pub fn stages(data_sources: &[ConfiguredDataSource]) -> Result<Vec<Vec<usize>>, DependencyCycle> {
+        let dependencies: Vec<Vec<usize>> = data_sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                (0..data_sources.len())
+                    .filter(|j| {
+                        *j != i && source.consumes.iter().any(|key| data_sources[*j].may_provide(key))
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut stage_of: Vec<Option<usize>> = vec![None; data_sources.len()];
+        let mut stages: Vec<Vec<usize>> = vec![];
+        while stage_of.iter().any(Option::is_none) {
+            let stage: Vec<usize> = (0..data_sources.len())
+                .filter(|i| {
+                    stage_of[*i].is_none()
+                        && dependencies[*i]
+                            .iter()
+                            .all(|j| stage_of[*j].is_some_and(|s| s < stages.len()))
+                })
+                .collect();
+            if stage.is_empty() {
+                // every remaining source waits for another remaining one, so following them leads into a cycle
+                let mut path = vec![stage_of.iter().position(Option::is_none).unwrap()];
+                loop {
+                    let last = *path.last().unwrap();
+                    let next = *dependencies[last]
+                        .iter()
+                        .find(|j| stage_of[**j].is_none())
+                        .unwrap();
+                    if let Some(start) = path.iter().position(|i| *i == next) {
+                        let mut sources: Vec<String> =
+                            path[start..].iter().map(|i| data_sources[*i].name.clone()).collect();
+                        sources.push(data_sources[next].name.clone());
+                        return Err(DependencyCycle { sources });
                     }
+                    path.push(next);
+                }
+            }
+            for i in &stage {
+                stage_of[*i] = Some(stages.len());
+            }
+            stages.push(stage);
+        }
+        Ok(stages)
+    }
+
+    /// Moves the fields of a report that are not configured or of another type out of the fused result,
+    /// logging each of them
+    pub fn verify(&self, source: &str, mut result: SearchResult) -> VerifiedResult {
+        let mut dropped = SearchResult::default();
+        let field_type = |key: &String| self.search_fields.get(key).map(|f| &f.field_type);
+        for (key, value) in std::mem::take(&mut result.numeric_fields) {
+            match field_type(&key) {
+                Some(FieldType::Float { .. }) => {
+                    result.numeric_fields.insert(key, value);
+                    continue;
+                }
+                Some(FieldType::String) => {
+                    eprintln!("Data source {}: ignoring numeric value of string field {}", source, key)
                 }
-            } else {
-                cloned_result.numeric_fields.remove(key);
+                None => eprintln!("Data source {}: ignoring unknown field {}", source, key),
             }
+            dropped.numeric_fields.insert(key, value);
+        }
+        for (key, value) in std::mem::take(&mut result.string_fields) {
+            match field_type(&key) {
+                Some(FieldType::String) => {
+                    result.string_fields.insert(key, value);
+                    continue;
+                }
+                Some(FieldType::Float { .. }) => {
+                    eprintln!("Data source {}: ignoring string value of numeric field {}", source, key)
+                }
+                None => eprintln!("Data source {}: ignoring unknown field {}", source, key),
+            }
+            dropped.string_fields.insert(key, value);
+        }
+        VerifiedResult { result, dropped }
+    }
+
+    /// Adds the configured units to the requested numeric fields
+    fn with_units(&self, mut request: SearchRequest) -> SearchRequest {
+        for field in request.fields.iter_mut().flatten() {
+            if let Some(Field {
+                field_type: FieldType::Float { unit },
+                ..
+            }) = self.search_fields.get(&field.name)
+            {
+                field.unit = Some(unit.clone());
+            }
+        }
+        request
+    }
+
+    /// Deadline for the answer of a source to a search started at `start`
+    fn deadline(&self, source: &ConfiguredDataSource, start: Instant) -> Option<Instant> {
+        match (
+            source.timeout.map(|t| start + t),
+            self.search_timeout.map(|t| start + t),
+        ) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Awaits the answer of a source until its deadline, logging failures
+    async fn within_deadline<T>(
+        &self,
+        source: &ConfiguredDataSource,
+        request: &SearchRequest,
+        deadline: Option<Instant>,
+        answer: impl Future<Output = Result<T, DataSourceError>>,
+    ) -> Result<T, DataSourceError> {
+        let answer = match deadline {
+            Some(deadline) => timeout_at(deadline, answer)
+                .await
+                .unwrap_or(Err(DataSourceError::Timeout)),
+            None => answer.await,
+        };
+        match &answer {
+            Ok(_) | Err(DataSourceError::NotFound) => {}
+            Err(e) => eprintln!("Data source {}: query {:?} failed: {}", source.name, request.query, e),
         }
-        for (key, _) in &result.string_fields {
-            if let Some(field) = self.search_fields.get(key) {
-                if field.field_type != FieldType::String {
-                    cloned_result.string_fields.remove(key);
+        answer
+    }
+
+    async fn ask(
+        &self,
+        source: &ConfiguredDataSource,
+        request: &SearchRequest,
+        deadline: Option<Instant>,
+    ) -> VerifiedAnswer {
+        self.within_deadline(source, request, deadline, source.data_source.search(request))
+            .await
+            .map(|result| self.verify(&source.name, result))
+    }
+
+    /// Adds the fields consumed by the sources queried for the requested fields, as these sources need them
+    fn with_consumed_fields(&self, mut request: SearchRequest) -> SearchRequest {
+        loop {
+            let consumed: HashSet<String> = self
+                .data_sources
+                .iter()
+                .filter(|source| source.can_contribute(&request))
+                .flat_map(|source| &source.consumes)
+                .filter(|key| !request.requests(key))
+                .cloned()
+                .collect();
+            match &mut request.fields {
+                Some(fields) if !consumed.is_empty() => {
+                    fields.extend(consumed.into_iter().map(RequestedField::new))
                 }
-            } else {
-                cloned_result.string_fields.remove(key);
+                _ => return request,
             }
         }
-        cloned_result
     }
-    pub async fn search(&self, query: String) -> SearchResponse {
-        let futures = self
-            .data_sources
+
+    /// Awaits the answers of a source to a batch of requests until its deadline, logging failures
+    async fn ask_many(
+        &self,
+        source: &ConfiguredDataSource,
+        requests: &[SearchRequest],
+        deadline: Option<Instant>,
+    ) -> Vec<VerifiedAnswer> {
+        let answers = source.data_source.search_many(requests);
+        let answers = match deadline {
+            Some(deadline) => timeout_at(deadline, answers)
+                .await
+                .unwrap_or_else(|_| vec![Err(DataSourceError::Timeout); requests.len()]),
+            None => answers.await,
+        };
+        let failures: Vec<&DataSourceError> = answers
             .iter()
-            .map(|source| source.search(query.clone()));
-        let results = join_all(futures).await;
-        let filtered_results: Vec<SearchResult> = results
+            .filter_map(|a| a.as_ref().err())
+            .filter(|e| **e != DataSourceError::NotFound)
+            .collect();
+        if let Some(e) = failures.first() {
+            eprintln!(
+                "Data source {}: {} of {} queries failed, e.g.: {}",
+                source.name,
+                failures.len(),
+                requests.len(),
+                e
+            );
+        }
+        answers
+            .into_iter()
+            .map(|answer| answer.map(|result| self.verify(&source.name, result)))
+            .collect()
+    }
+
+    /// Queries the data sources stage by stage, passing each source the fused values of the fields it consumes
+    /// from the answers of the earlier stages. A source asked several requests gets them as one batch.
+    /// Answers are stored at the index of their request and source
+    async fn ask_stages(
+        &self,
+        requests: &[SearchRequest],
+        stages: &[Vec<usize>],
+        reports: &mut [Vec<Option<VerifiedAnswer>>],
+        start: Instant,
+    ) {
+        for stage in stages {
+            let consuming = stage.iter().any(|i| !self.data_sources[*i].consumes.is_empty());
+            let fused: Vec<Option<SearchResponse>> =
+                reports.iter().map(|r| consuming.then(|| self.fuse(r, false))).collect();
+            let futures = stage.iter().map(|i| {
+                let source = &self.data_sources[*i];
+                let (indices, requests): (Vec<usize>, Vec<SearchRequest>) = requests
+                    .iter()
+                    .zip(&fused)
+                    .map(|(request, fused)| source.request_with_context(request, fused.as_ref()))
+                    .enumerate()
+                    .filter(|(_, request)| source.can_contribute(request))
+                    .unzip();
+                async move {
+                    let deadline = self.deadline(source, start);
+                    let answers = match requests.as_slice() {
+                        [] => vec![],
+                        [request] => vec![self.ask(source, request, deadline).await],
+                        _ => self.ask_many(source, &requests, deadline).await,
+                    };
+                    (*i, indices, answers)
+                }
+            });
+            for (i, indices, answers) in join_all(futures).await {
+                for (j, answer) in indices.into_iter().zip(answers) {
+                    reports[j][i] = Some(answer);
+                }
+            }
+        }
+    }
+
+    /// Queries the data sources that can report any of the requested fields. Returns the verified answer of each source,
+    /// at the same index as the source in `data_sources`, or `None` if it was not queried.
+    /// Sources missing their own or the global deadline are not awaited
+    pub async fn collect_reports(&self, request: impl Into<SearchRequest>) -> Vec<Option<VerifiedAnswer>> {
+        let mut reports = self.collect_many_reports(vec![request.into()]).await;
+        reports.pop().unwrap()
+    }
+
+    /// Reports of `collect_reports` as if the source at index `skipped` was not configured.
+    /// The sources of the later stages are asked again, since the fields they consume may change without it
+    pub async fn collect_reports_without(
+        &self,
+        request: impl Into<SearchRequest>,
+        reports: &[Option<VerifiedAnswer>],
+        skipped: usize,
+    ) -> Vec<Option<VerifiedAnswer>> {
+        let request = self.with_units(self.with_consumed_fields(request.into()));
+        let later_stages = match self.stages.iter().position(|stage| stage.contains(&skipped)) {
+            Some(stage) => &self.stages[stage + 1..],
+            None => &[],
+        };
+        let mut reports = vec![reports.to_vec()];
+        reports[0][skipped] = None;
+        for i in later_stages.iter().flatten() {
+            reports[0][*i] = None;
+        }
+        self.ask_stages(std::slice::from_ref(&request), later_stages, &mut reports, Instant::now())
+            .await;
+        reports.pop().unwrap()
+    }
+
+    /// Same as `collect_reports` for several requests, sending each data source all its requests in one batch.
+    /// The deadlines apply to the batch
+    pub async fn collect_many_reports(&self, requests: Vec<SearchRequest>) -> Vec<Vec<Option<VerifiedAnswer>>> {
+        let requests: Vec<SearchRequest> = requests
             .into_iter()
-            .filter_map(|r| r)
-            .map(|r| self.verify(&r))
+            .map(|request| self.with_units(self.with_consumed_fields(request)))
             .collect();
-        let merged = SearchResult::merge(&filtered_results);
+        let mut reports = vec![vec![None; self.data_sources.len()]; requests.len()];
+        self.ask_stages(&requests, &self.stages, &mut reports, Instant::now())
+            .await;
+        reports
+    }
+
+    fn source_reports(&self, fused: &[Option<SearchResult>], skipped: Option<usize>) -> Vec<SourceReport> {
+        fused
+            .iter()
+            .zip(&self.data_sources)
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != skipped)
+            .filter_map(|(_, (r, source))| {
+                r.clone().map(|result| SourceReport {
+                    result,
+                    weight: source.weight,
+                    correlation_group: source.correlation_group.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Records the contribution of each source to each field.
+    /// The influence of a source is found by fusing the reports of all other sources
+    fn explain(
+        &self,
+        reports: &[Option<VerifiedAnswer>],
+        fused: &[Option<SearchResult>],
+        merged: &SearchResult,
+    ) -> HashMap<String, Vec<FieldProvenance>> {
+        let mut provenance: HashMap<String, Vec<FieldProvenance>> = HashMap::new();
+        for (i, ((report, fused_report), source)) in reports
+            .iter()
+            .zip(fused)
+            .zip(&self.data_sources)
+            .enumerate()
+        {
+            let report = match (report, fused_report) {
+                (Some(Ok(report)), Some(_)) => report,
+                _ => continue,
+            };
+            let without = SearchResult::merge_reports(&self.source_reports(fused, Some(i)));
+            let numeric_fields = report.result.numeric_fields.iter().map(|(k, v)| (k, v, false));
+            let dropped_numeric_fields = report.dropped.numeric_fields.iter().map(|(k, v)| (k, v, true));
+            for (key, value, dropped) in numeric_fields.chain(dropped_numeric_fields) {
+                let influence = match (merged.numeric_fields.get(key), dropped) {
+                    (Some(fused), false) => match without.numeric_fields.get(key) {
+                        Some(other) => fused.total_variation_distance(other),
+                        None => (!matches!(fused, NumericFieldValue::Error)).then_some(1.0),
+                    },
+                    _ => None,
+                };
+                provenance.entry(key.clone()).or_default().push(FieldProvenance {
+                    source: source.name.clone(),
+                    reported: FieldValue::Numeric(value.clone()),
+                    dropped,
+                    influence,
+                });
+            }
+            let string_fields = report.result.string_fields.iter().map(|(k, v)| (k, v, false));
+            let dropped_string_fields = report.dropped.string_fields.iter().map(|(k, v)| (k, v, true));
+            for (key, value, dropped) in string_fields.chain(dropped_string_fields) {
+                let influence = match (merged.string_fields.get(key), dropped) {
+                    (Some(fused), false) => match without.string_fields.get(key) {
+                        Some(other) => fused.total_variation_distance(other),
+                        None => (!matches!(fused, StringFieldValue::Error)).then_some(1.0),
+                    },
+                    _ => None,
+                };
+                provenance.entry(key.clone()).or_default().push(FieldProvenance {
+                    source: source.name.clone(),
+                    reported: FieldValue::String(value.clone()),
+                    dropped,
+                    influence,
+                });
+            }
+        }
+        provenance
+    }
+
+    /// Calibrates and fuses the reports returned by `collect_reports`, recording the provenance of the fields
+    /// if `explain` is set. Sources without answer yet are left out
+    pub fn fuse(&self, reports: &[Option<VerifiedAnswer>], explain: bool) -> SearchResponse {
+        let mut fused: Vec<Option<SearchResult>> = reports
+            .iter()
+            .zip(&self.data_sources)
+            .map(|(r, source)| match r {
+                Some(Ok(r)) => Some(source.calibrate(r.result.clone())),
+                _ => None,
+            })
+            .collect();
+        let linkage = entity_resolution::link(
+            &fused.iter().map(|r| r.as_ref().map(|r| &r.ids)).collect::<Vec<_>>(),
+        );
+        let mut conflicts = vec![];
+        for (i, (report, source)) in fused.iter_mut().zip(&self.data_sources).enumerate() {
+            if linkage.linked.contains(&i) {
+                continue;
+            }
+            if let Some(result) = report.take() {
+                if let Some(scheme) = entity_resolution::conflicting_scheme(&result.ids, &linkage.ids) {
+                    conflicts.push(LinkageConflict {
+                        source: source.name.clone(),
+                        scheme: scheme.clone(),
+                        id: result.ids[scheme].clone(),
+                        linked_id: linkage.ids[scheme].clone(),
+                    });
+                }
+            }
+        }
+        let merged = SearchResult::merge_reports(&self.source_reports(&fused, None));
+        let provenance = match explain {
+            true => self.explain(reports, &fused, &merged),
+            false => HashMap::new(),
+        };
 
         SearchResponse {
+            ids: merged.ids,
             fields: merged
                 .numeric_fields
                 .into_iter()
@@ -94,6 +675,601 @@ impl SearchEngine {
                         .map(|field| (k.clone(), (field.clone(), FieldValue::String(v))))
                 }))
                 .collect(),
+            provenance,
+            conflicts,
+            sources: reports
+                .iter()
+                .zip(&self.data_sources)
+                .filter_map(|(r, source)| {
+                    r.as_ref().map(|r| SourceOutcome {
+                        source: source.name.clone(),
+                        error: r.as_ref().err().cloned(),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub async fn search(&self, request: impl Into<SearchRequest>) -> SearchResponse {
+        let request = request.into();
+        let explain = request.explain;
+        let reports = self.collect_reports(request).await;
+        self.fuse(&reports, explain)
+    }
+
+    /// Searches several entities, returning the responses in the order of the requests.
+    /// The requests are split into batches of `BATCH_SIZE`, of which at most `concurrency` are searched at the same time
+    pub async fn search_many(&self, requests: Vec<SearchRequest>, concurrency: usize) -> Vec<SearchResponse> {
+        let batches: Vec<Vec<SearchRequest>> = requests.chunks(BATCH_SIZE).map(<[_]>::to_vec).collect();
+        stream::iter(batches)
+            .map(|batch| async move {
+                let explain: Vec<bool> = batch.iter().map(|request| request.explain).collect();
+                let reports = self.collect_many_reports(batch).await;
+                stream::iter(
+                    reports
+                        .iter()
+                        .zip(explain)
+                        .map(|(r, explain)| self.fuse(r, explain))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .buffered(concurrency.max(1))
+            .flatten()
+            .collect()
+            .await
+    }
+
+    /// Searches all entities matching the query. Candidates of different sources describing the same entity
+    /// are fused, and the fused entities are ranked by the probability of being the one searched for.
+    /// Sources consuming fused fields are asked once per entity, with the fields fused for that entity
+    pub async fn search_candidates(&self, request: impl Into<SearchRequest>) -> Vec<EntityCandidate> {
+        let request = self.with_units(self.with_consumed_fields(request.into()));
+        let start = Instant::now();
+        let futures = self.data_sources.iter().map(|source| {
+            let request = &request;
+            async move {
+                if !source.consumes.is_empty() || !source.can_contribute(request) {
+                    return None;
+                }
+                let deadline = self.deadline(source, start);
+                let candidates = source.data_source.search_candidates(request);
+                Some(self.within_deadline(source, request, deadline, candidates).await)
+            }
+        });
+        let answers = join_all(futures).await;
+        let candidates = answers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, a)| match a {
+                Some(Ok(candidates)) => Some(candidates.iter().map(move |c| (i, c.clone()))),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let clusters = entity_resolution::cluster(candidates);
+        let probabilities = entity_resolution::match_probabilities(&clusters);
+        let dependent_stages: Vec<Vec<usize>> = self
+            .stages
+            .iter()
+            .map(|stage| {
+                stage
+                    .iter()
+                    .copied()
+                    .filter(|i| !self.data_sources[*i].consumes.is_empty())
+                    .collect()
+            })
+            .collect();
+        let entities = clusters
+            .into_iter()
+            .zip(probabilities)
+            .map(|(cluster, probability)| {
+                // reports of the single request of the entity
+                let mut reports: Vec<Vec<Option<VerifiedAnswer>>> = vec![answers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, answer)| match answer {
+                        Some(Ok(_)) => Some(
+                            cluster
+                                .members
+                                .iter()
+                                .find(|(s, _)| *s == i)
+                                .map(|(_, c)| self.verify(&self.data_sources[i].name, c.result.clone()))
+                                .ok_or(DataSourceError::NotFound),
+                        ),
+                        Some(Err(e)) => Some(Err(e.clone())),
+                        None => None,
+                    })
+                    .collect()];
+                let request = &request;
+                let dependent_stages = &dependent_stages;
+                async move {
+                    self.ask_stages(std::slice::from_ref(request), dependent_stages, &mut reports, start)
+                        .await;
+                    EntityCandidate {
+                        label: cluster.label().cloned(),
+                        probability,
+                        response: self.fuse(&reports[0], request.explain),
+                    }
+                }
+            });
+        let mut entities = join_all(entities).await;
+        entities.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+        entities
+    }
+
+    /// Emits a new fusion of the answers received so far each time a data source answers.
+    /// The sources of a stage are queried once all sources of the earlier stages answered.
+    /// The last item is the same as the result of `search`. If no source is queried, it is the only item
+    pub fn search_progressively(
+        self: Arc<Self>,
+        request: impl Into<SearchRequest>,
+    ) -> impl Stream<Item = SearchResponse> {
+        let request = self.with_units(self.with_consumed_fields(request.into()));
+        let start = Instant::now();
+        let pending: FuturesUnordered<BoxFuture<'static, (usize, VerifiedAnswer)>> = FuturesUnordered::new();
+        let answers = vec![None; self.data_sources.len()];
+        let state = (pending, answers, 0, false);
+        stream::unfold(state, move |(mut pending, mut answers, mut next_stage, emitted)| {
+            let engine = self.clone();
+            let request = request.clone();
+            async move {
+                while pending.is_empty() {
+                    let stage = match engine.stages.get(next_stage) {
+                        Some(stage) => stage,
+                        None if emitted => return None,
+                        None => {
+                            let response = engine.fuse(&answers, request.explain);
+                            return Some((response, (pending, answers, next_stage, true)));
+                        }
+                    };
+                    next_stage += 1;
+                    let fused = stage
+                        .iter()
+                        .any(|i| !engine.data_sources[*i].consumes.is_empty())
+                        .then(|| engine.fuse(&answers, false));
+                    for i in stage.iter().copied() {
+                        let request = engine.data_sources[i].request_with_context(&request, fused.as_ref());
+                        if !engine.data_sources[i].can_contribute(&request) {
+                            continue;
+                        }
+                        let engine = engine.clone();
+                        pending.push(Box::pin(async move {
+                            let source = &engine.data_sources[i];
+                            let answer = engine
+                                .ask(source, &request, engine.deadline(source, start))
+                                .await;
+                            (i, answer)
+                        }));
+                    }
+                }
+                let (i, answer) = pending.next().await?;
+                answers[i] = Some(answer);
+                let response = engine.fuse(&answers, request.explain);
+                Some((response, (pending, answers, next_stage, true)))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::datasources::mock_datasource::{MockDataSource, MockRecord};
+
+    fn source(
+        name: &str,
+        provides: &str,
+        consumes: &[&str],
+        data_source: impl DataSource + Sync + Send + 'static,
+    ) -> ConfiguredDataSource {
+        ConfiguredDataSource {
+            name: name.to_string(),
+            description: None,
+            tags: vec![],
+            data_source: Box::new(data_source),
+            confusion_matrices: HashMap::new(),
+            weight: 1.0,
+            calibration: HashMap::new(),
+            correlation_group: None,
+            timeout: None,
+            provides: Some(HashSet::from([provides.to_string()])),
+            consumes: consumes.iter().map(|key| key.to_string()).collect(),
+        }
+    }
+
+    fn field(description: &str, field_type: FieldType) -> Field {
+        Field {
+            description: description.to_string(),
+            field_type,
+        }
+    }
+
+    #[tokio::test]
+    async fn chained_sources() {
+        let categories = MockDataSource {
+            data: HashMap::from([(
+                "apple".to_string(),
+                SearchResult {
+                    string_fields: HashMap::from([(
+                        "category".to_string(),
+                        StringFieldValue::Exact("fruit".to_string()),
+                    )]),
+                    ..Default::default()
+                },
+            )]),
+            records: vec![],
+        };
+        let by_category = MockDataSource {
+            data: HashMap::new(),
+            records: vec![MockRecord {
+                keys: HashMap::from([("category".to_string(), "fruit".to_string())]),
+                label: None,
+                score: 1.0,
+                result: SearchResult {
+                    numeric_fields: HashMap::from([(
+                        "energy_density".to_string(),
+                        NumericFieldValue::Exact(2000.0),
+                    )]),
+                    ..Default::default()
+                },
+            }],
+        };
+        let engine = SearchEngine::new(
+            HashMap::from([
+                ("category".to_string(), field("Category", FieldType::String)),
+                (
+                    "energy_density".to_string(),
+                    field("Energy", FieldType::Float { unit: "KJ/kg".to_string() }),
+                ),
+            ]),
+            vec![
+                source("by_category", "energy_density", &["category"], by_category),
+                source("categories", "category", &[], categories),
+            ],
+            None,
+        )
+        .unwrap();
+        assert_eq!(engine.stages, vec![vec![1], vec![0]]);
+        let response = engine
+            .search(SearchRequest {
+                query: "apple".to_string(),
+                fields: Some(vec![RequestedField::new("energy_density".to_string())]),
+                ..Default::default()
+            })
+            .await;
+        assert!(
+            matches!(response.fields["energy_density"].1, FieldValue::Numeric(NumericFieldValue::Exact(e)) if e == 2000.0),
+            "consumed field not passed on"
+        );
+        let reports = engine.collect_reports("apple".to_string()).await;
+        assert!(matches!(reports[0], Some(Ok(_))));
+        // without the categories, the consuming source is asked again and finds nothing
+        let ablated = engine.collect_reports_without("apple".to_string(), &reports, 1).await;
+        assert!(ablated[1].is_none());
+        assert!(!matches!(ablated[0], Some(Ok(_))));
+    }
+
+    /// Reports the length of the query as weight, counting the batches it receives
+    struct Batching {
+        batches: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl DataSource for Batching {
+        async fn search(&self, request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+            Ok(SearchResult {
+                numeric_fields: HashMap::from([(
+                    "weight".to_string(),
+                    NumericFieldValue::Exact(request.query.len() as f64),
+                )]),
+                ..Default::default()
+            })
+        }
+
+        async fn search_many(&self, requests: &[SearchRequest]) -> Vec<SourceAnswer> {
+            self.batches.fetch_add(1, Ordering::Relaxed);
+            join_all(requests.iter().map(|request| self.search(request))).await
+        }
+    }
+
+    #[tokio::test]
+    async fn search_many() {
+        let batches = Arc::new(AtomicUsize::new(0));
+        let batching = Batching {
+            batches: batches.clone(),
+        };
+        let engine = SearchEngine::new(
+            HashMap::from([(
+                "weight".to_string(),
+                field("Weight", FieldType::Float { unit: "kg".to_string() }),
+            )]),
+            vec![source("batching", "weight", &[], batching)],
+            None,
+        )
+        .unwrap();
+        let queries = ["a", "abc", "ab"];
+        let responses = engine
+            .search_many(queries.iter().map(|q| q.to_string().into()).collect(), 2)
+            .await;
+        let weights: Vec<f64> = responses
+            .iter()
+            .map(|r| match &r.fields["weight"].1 {
+                FieldValue::Numeric(v) => v.mu(),
+                _ => f64::NAN,
+            })
+            .collect();
+        assert_eq!(weights, vec![1.0, 3.0, 2.0], "responses not in the order of the requests");
+        assert_eq!(batches.load(Ordering::Relaxed), 1, "requests not sent as one batch");
+    }
+
+    /// Reports an exact value of one numeric field after a delay
+    struct Delayed {
+        delay: Duration,
+        key: &'static str,
+        value: f64,
+    }
+
+    #[async_trait::async_trait]
+    impl DataSource for Delayed {
+        async fn search(&self, _request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+            tokio::time::sleep(self.delay).await;
+            Ok(SearchResult {
+                numeric_fields: HashMap::from([(self.key.to_string(), NumericFieldValue::Exact(self.value))]),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn delayed(name: &str, millis: u64, key: &'static str, value: f64) -> ConfiguredDataSource {
+        let data_source = Delayed {
+            delay: Duration::from_millis(millis),
+            key,
+            value,
+        };
+        source(name, key, &[], data_source)
+    }
+
+    fn weight_and_size() -> SearchFields {
+        HashMap::from([
+            ("weight".to_string(), field("Weight", FieldType::Float { unit: "kg".to_string() })),
+            ("size".to_string(), field("Size", FieldType::Float { unit: "m".to_string() })),
+        ])
+    }
+
+    #[tokio::test]
+    async fn progressive_search() {
+        let engine = Arc::new(
+            SearchEngine::new(
+                weight_and_size(),
+                vec![delayed("slow", 50, "size", 2.0), delayed("fast", 0, "weight", 1.0)],
+                None,
+            )
+            .unwrap(),
+        );
+        let responses: Vec<SearchResponse> =
+            engine.clone().search_progressively("apple".to_string()).collect().await;
+        let keys: Vec<Vec<&String>> = responses
+            .iter()
+            .map(|r| {
+                let mut keys: Vec<&String> = r.fields.keys().collect();
+                keys.sort();
+                keys
+            })
+            .collect();
+        assert_eq!(keys, vec![vec!["weight"], vec!["size", "weight"]], "not emitted once per answer");
+
+        let unrouted = SearchRequest {
+            query: "apple".to_string(),
+            fields: Some(vec![RequestedField::new("color".to_string())]),
+            ..Default::default()
+        };
+        let responses: Vec<SearchResponse> = engine.search_progressively(unrouted).collect().await;
+        assert_eq!(responses.len(), 1, "nothing emitted without queried sources");
+        assert!(responses[0].fields.is_empty() && responses[0].sources.is_empty());
+    }
+
+    #[tokio::test]
+    async fn deadlines() {
+        let mut own_timeout = delayed("own_timeout", 1000, "size", 2.0);
+        own_timeout.timeout = Some(Duration::from_millis(20));
+        let engine = SearchEngine::new(
+            weight_and_size(),
+            vec![
+                own_timeout,
+                delayed("search_timeout", 1000, "size", 3.0),
+                delayed("fast", 0, "weight", 1.0),
+            ],
+            Some(Duration::from_millis(100)),
+        )
+        .unwrap();
+        let start = Instant::now();
+        let response = engine.search("apple".to_string()).await;
+        assert!(start.elapsed() < Duration::from_millis(500), "slow sources awaited");
+        let outcomes: HashMap<&str, Option<&DataSourceError>> = response
+            .sources
+            .iter()
+            .map(|o| (o.source.as_str(), o.error.as_ref()))
+            .collect();
+        assert_eq!(outcomes["own_timeout"], Some(&DataSourceError::Timeout));
+        assert_eq!(outcomes["search_timeout"], Some(&DataSourceError::Timeout));
+        assert_eq!(outcomes["fast"], None);
+        assert!(
+            matches!(response.fields["weight"].1, FieldValue::Numeric(NumericFieldValue::Exact(w)) if w == 1.0),
+            "answer of the remaining source not fused"
+        );
+        assert!(!response.fields.contains_key("size"));
+    }
+
+    /// Fails every query with the given error
+    struct Failing(DataSourceError);
+
+    #[async_trait::async_trait]
+    impl DataSource for Failing {
+        async fn search(&self, _request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+            Err(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn failures() {
+        let errors = [
+            DataSourceError::NotFound,
+            DataSourceError::Transport("connection refused".to_string()),
+            DataSourceError::Decode("unexpected end of input".to_string()),
+            DataSourceError::Timeout,
+        ];
+        let mut sources: Vec<ConfiguredDataSource> = errors
+            .iter()
+            .map(|e| source(e.name(), "weight", &[], Failing(e.clone())))
+            .collect();
+        sources.push(delayed("working", 0, "size", 2.0));
+        let engine = SearchEngine::new(weight_and_size(), sources, None).unwrap();
+        let response = engine.search("apple".to_string()).await;
+        let outcomes: HashMap<&str, Option<&DataSourceError>> = response
+            .sources
+            .iter()
+            .map(|o| (o.source.as_str(), o.error.as_ref()))
+            .collect();
+        assert_eq!(outcomes.len(), errors.len() + 1);
+        for e in &errors {
+            assert_eq!(outcomes[e.name()], Some(e), "wrong outcome of source {}", e.name());
+        }
+        assert_eq!(outcomes["working"], None);
+        assert!(response.fields.contains_key("size"));
+        assert!(!response.fields.contains_key("weight"));
+    }
+
+    fn mock(numeric_fields: &[(&str, NumericFieldValue)]) -> MockDataSource {
+        let result = SearchResult {
+            numeric_fields: numeric_fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        MockDataSource {
+            data: HashMap::from([("apple".to_string(), result)]),
+            records: vec![],
         }
     }
+
+    #[tokio::test]
+    async fn provenance() {
+        let precise = mock(&[
+            ("weight", NumericFieldValue::Normal { sigma: 1.0, mu: 1.0 }),
+            ("volume", NumericFieldValue::Exact(2.0)),
+        ]);
+        let vague = mock(&[("weight", NumericFieldValue::Normal { sigma: 5.0, mu: 0.0 })]);
+        let engine = SearchEngine::new(
+            weight_and_size(),
+            vec![source("precise", "weight", &[], precise), source("vague", "weight", &[], vague)],
+            None,
+        )
+        .unwrap();
+        let unexplained = engine.search("apple".to_string()).await;
+        assert!(unexplained.provenance.is_empty(), "provenance computed without being requested");
+
+        let explained = engine
+            .search(SearchRequest {
+                query: "apple".to_string(),
+                explain: true,
+                ..Default::default()
+            })
+            .await;
+        let influence = |source: &str| {
+            explained.provenance["weight"]
+                .iter()
+                .find(|p| p.source == source)
+                .and_then(|p| p.influence)
+                .unwrap()
+        };
+        assert!(influence("precise") > 0.5, "precise source has too little influence");
+        assert!(influence("vague") < 0.1, "vague source has too much influence");
+        let volume = &explained.provenance["volume"];
+        assert_eq!(volume.len(), 1);
+        assert!(volume[0].dropped && volume[0].influence.is_none(), "unknown field not dropped");
+
+        let failing = SearchEngine::new(
+            weight_and_size(),
+            vec![source("failing", "weight", &[], mock(&[("weight", NumericFieldValue::Error)]))],
+            None,
+        )
+        .unwrap();
+        let explained = failing
+            .search(SearchRequest {
+                query: "apple".to_string(),
+                explain: true,
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(explained.provenance["weight"][0].influence, None, "influence on an error");
+    }
+
+    /// Records the fields requested from it
+    struct Recording {
+        requested: Arc<std::sync::Mutex<Vec<Option<Vec<RequestedField>>>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl DataSource for Recording {
+        async fn search(&self, request: &SearchRequest) -> Result<SearchResult, DataSourceError> {
+            self.requested.lock().unwrap().push(request.fields.clone());
+            Err(DataSourceError::NotFound)
+        }
+    }
+
+    #[tokio::test]
+    async fn routing() {
+        let requested = Arc::new(std::sync::Mutex::new(vec![]));
+        let recording = Recording {
+            requested: requested.clone(),
+        };
+        let engine = SearchEngine::new(
+            weight_and_size(),
+            vec![source("weights", "weight", &[], recording), delayed("sizes", 0, "size", 2.0)],
+            None,
+        )
+        .unwrap();
+        let queried = |response: &SearchResponse| -> Vec<String> {
+            let mut sources: Vec<String> = response.sources.iter().map(|o| o.source.clone()).collect();
+            sources.sort();
+            sources
+        };
+        let weight_only = SearchRequest {
+            query: "apple".to_string(),
+            fields: Some(vec![RequestedField::new("weight".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(
+            queried(&engine.search(weight_only).await),
+            ["weights"],
+            "source without requested field queried"
+        );
+        assert_eq!(
+            requested.lock().unwrap()[0],
+            Some(vec![RequestedField {
+                name: "weight".to_string(),
+                unit: Some("kg".to_string()),
+            }]),
+            "requested field not passed on with its unit"
+        );
+        let all = engine.search("apple".to_string()).await;
+        assert_eq!(queried(&all), ["sizes", "weights"], "sources skipped without requested fields");
+    }
+
+    #[test]
+    fn dependency_cycle() {
+        let empty = || MockDataSource {
+            data: HashMap::new(),
+            records: vec![],
+        };
+        let cycle = SearchEngine::stages(&[
+            source("a", "category", &["weight"], empty()),
+            source("b", "weight", &["category"], empty()),
+        ])
+        .unwrap_err();
+        assert_eq!(cycle.sources, vec!["a", "b", "a"]);
+    }
 }
@@ -1,54 +1,910 @@
 use crate::{
     datasource::DataSource,
     datasources::{
-        grpc_datasource::GrpcDataSource, mock_datasource::MockDataSource, rest_datasource::RestDatasource,
+        cached_datasource::CachedDataSource, grpc_datasource::GrpcDataSource,
+        mock_datasource::MockDataSource, resilient_datasource::ResilientDataSource,
+        rest_datasource::RestDatasource,
+        unavailable_datasource::UnavailableDataSource,
     },
-    search_engine::{Field, SearchEngine},
+    search_engine::{ConfiguredDataSource, DependencyCycle, Field, FieldType, SearchEngine},
+    search_result::SearchResult,
+    string::ConfusionMatrix,
+};
+use schemars::JsonSchema;
+use serde::{
+    de::{IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::Duration,
 };
-use serde::Deserialize;
-use std::{collections::HashMap, path::PathBuf};
 type FieldsConfig = HashMap<String, Field>;
 
-#[derive(Debug, Clone, Deserialize)]
-pub enum DataSourceConfig {
+/// Time waited for a remote source to answer the reachability check
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub enum DataSourceType {
     // OpenFoodFacts,
-    Mock { data_path: PathBuf },
-    Grpc { address: String },
-    Rest { base_url: String },
+    Mock {
+        /// File of fixed results, relative to the working directory
+        data_path: PathBuf,
+    },
+    Grpc {
+        /// Address of the gRPC server, e.g. `http://localhost:50051`. May refer to environment variables as `${VAR}`
+        address: String,
+    },
+    Rest {
+        /// URL the `/search` path is appended to. May refer to environment variables as `${VAR}`
+        base_url: String,
+    },
+}
+
+impl std::fmt::Display for DataSourceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataSourceType::Mock { data_path } => write!(f, "Mock({})", data_path.display()),
+            DataSourceType::Grpc { address } => write!(f, "Grpc({})", address),
+            DataSourceType::Rest { base_url } => write!(f, "Rest({})", base_url),
+        }
+    }
+}
+
+/// Correction of the systematic error of a data source for one numeric field
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct NumericCalibration {
+    /// Average amount by which the source overestimates the true value, subtracted from its reports
+    #[serde(default)]
+    pub bias: f64,
+    /// Factor applied to the spread of the reported distributions
+    #[serde(default = "default_sigma_inflation")]
+    pub sigma_inflation: f64,
+}
+
+/// Cache of the results of a data source
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct CacheConfig {
+    /// Seconds after which a cached result is fetched again
+    pub ttl_seconds: u64,
+    /// Maximum number of results kept in memory. Defaults to 1000
+    #[serde(default = "default_cache_capacity")]
+    pub capacity: usize,
+    /// Directory of an embedded database keeping the cached results across restarts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persist_path: Option<PathBuf>,
+}
+
+/// Retries and circuit breaker around a remote data source.
+/// Only timeouts and transport errors count as failed queries. Each attempt times out after the `timeout_ms` of the source
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ResilienceConfig {
+    /// Number of times a failed query is repeated. Defaults to 2
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Milliseconds waited before the first retry. Defaults to 100
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Factor by which the wait grows after each retry. Defaults to 2
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: f64,
+    /// Upper bound of the wait between retries in milliseconds. Defaults to 2000
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Number of consecutive failed queries after which the circuit breaker opens. Defaults to 5
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Milliseconds during which queries fail fast before a probing query is let through. Defaults to 30000
+    #[serde(default = "default_open_duration_ms")]
+    pub open_duration_ms: u64,
+}
+
+/// Data source queried by the search engine, and how its reports are weighted and corrected
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct DataSourceConfig {
+    /// Unique name of the data source, used in logs, errors and provenance records
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub source: DataSourceType,
+    /// Keys of the fields this source can report. It is only queried by searches requesting one of them.
+    /// Defaults to the fields announced by the source, or all fields if it announces none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provides: Option<Vec<String>>,
+    /// Keys of fields fused from the other sources that this source needs, e.g. a category.
+    /// It is queried after the sources that may provide them and receives their current fused values
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub consumes: Vec<String>,
+    /// Confusion matrices `P(reported | true)` of string fields reported by this source, keyed by field name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub confusion_matrices: HashMap<String, ConfusionMatrix>,
+    /// Exponent applied to the likelihoods reported by this source during fusion.
+    /// Values below 1 downweight an unreliable source, 0 ignores it. Must not be negative. Defaults to 1
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    /// Corrections of numeric fields reported by this source, keyed by field name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub calibration: HashMap<String, NumericCalibration>,
+    /// Sources in the same correlation group share evidence, e.g. because they scrape the same upstream database.
+    /// Their reports are averaged before being combined with the other sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_group: Option<String>,
+    /// Milliseconds after which the answer of this source is no longer waited for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Cache results of this source. Disabled if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheConfig>,
+    /// Retry failed queries and stop querying the source while it is down. Disabled if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resilience: Option<ResilienceConfig>,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_sigma_inflation() -> f64 {
+    1.0
+}
+
+fn default_cache_capacity() -> usize {
+    1000
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    100
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_max_backoff_ms() -> u64 {
+    2000
+}
+
+fn default_failure_threshold() -> u32 {
+    5
 }
 
-#[derive(Debug, Clone, Deserialize)]
+fn default_open_duration_ms() -> u64 {
+    30000
+}
+
+/// Fields of the search engine and the data sources reporting them
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct Config {
+    #[serde(default)]
     pub fields: FieldsConfig,
+    /// Files defining further fields, each a mapping from the field key to its definition.
+    /// Relative paths start from the working directory
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
     pub data_sources: Vec<DataSourceConfig>,
+    /// Milliseconds after which a search returns with the answers received so far
+    #[serde(default)]
+    pub search_timeout_ms: Option<u64>,
+    /// Problems found while loading, reported by `validate`
+    #[serde(skip)]
+    load_errors: Vec<ConfigError>,
 }
 
-impl Config {
-    pub async fn to_search_engine(self) -> SearchEngine {
-        let mut new_ds = Vec::with_capacity(self.data_sources.len());
-        for data_source in self.data_sources {
-            let m: Box<dyn DataSource + Sync + Send> = match data_source {
-                // DataSourceConfig::OpenFoodFacts => Box::new(OpenFoodFactsDataSource::default()),
-                DataSourceConfig::Grpc { address } => {
-                    let ds = GrpcDataSource::new(address).await;
-                    match ds {
-                        Ok(ds) => Box::new(ds),
-                        Err(e) => {
-                            println!("Failed to connect to gRPC server: {}", e);
-                            continue;
-                        }
+/// Result of a Mock file, identified by its free text query or by the keys of its record
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockEntry {
+    Query(String),
+    Record(BTreeMap<String, String>),
+}
+
+impl std::fmt::Display for MockEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MockEntry::Query(query) => write!(f, "result for {}", query),
+            MockEntry::Record(keys) => {
+                let keys: Vec<String> = keys.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                write!(f, "record {}", keys.join(", "))
+            }
+        }
+    }
+}
+
+/// Problem found in a config or in the Mock files it references
+#[derive(Debug, Clone)]
+pub enum ConfigError {
+    /// A file cannot be read or parsed
+    Unreadable { file: PathBuf, reason: String },
+    /// The same key is used for several fields of a file, or in an included file and the config
+    DuplicateField { file: PathBuf, key: String },
+    /// Several data sources have the same name
+    DuplicateDataSource { name: String },
+    /// A setting of a data source refers to a field that is not configured
+    UnknownField {
+        source: String,
+        setting: &'static str,
+        key: String,
+    },
+    /// A setting of a data source only applies to fields of another type, e.g. a confusion matrix of a numeric field
+    MistypedField {
+        source: String,
+        setting: &'static str,
+        key: String,
+    },
+    /// The weight of a data source is negative or not a number
+    InvalidWeight { source: String, weight: f64 },
+    /// A calibration scales the spread of reports by a factor that is not positive
+    InvalidCalibration {
+        source: String,
+        key: String,
+        sigma_inflation: f64,
+    },
+    /// A Mock file reports a field that is not configured
+    UnknownMockField {
+        file: PathBuf,
+        entry: MockEntry,
+        key: String,
+    },
+    /// A Mock file reports a numeric value for a string field or the other way round
+    MistypedMockField {
+        file: PathBuf,
+        entry: MockEntry,
+        key: String,
+    },
+    /// A Mock file reports a value that is not a proper distribution, e.g. a negative sigma or a uniform distribution with min > max
+    InvalidMockValue {
+        file: PathBuf,
+        entry: MockEntry,
+        reason: String,
+    },
+    /// The address of a data source refers to an environment variable that is not set
+    UndefinedVariable { source: String, variable: String },
+    /// A remote data source does not answer
+    Unreachable { source: String, reason: String },
+    /// Data sources consume fields they may provide to each other
+    DependencyCycle(DependencyCycle),
+}
+
+impl ConfigError {
+    /// File the problem is in, if it is not the config file itself
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            ConfigError::Unreadable { file, .. }
+            | ConfigError::DuplicateField { file, .. }
+            | ConfigError::UnknownMockField { file, .. }
+            | ConfigError::MistypedMockField { file, .. }
+            | ConfigError::InvalidMockValue { file, .. } => Some(file),
+            _ => None,
+        }
+    }
+
+    /// Name of the data source whose settings are wrong, if any
+    pub fn data_source(&self) -> Option<&str> {
+        match self {
+            ConfigError::DuplicateDataSource { name: source }
+            | ConfigError::UnknownField { source, .. }
+            | ConfigError::MistypedField { source, .. }
+            | ConfigError::InvalidWeight { source, .. }
+            | ConfigError::InvalidCalibration { source, .. }
+            | ConfigError::UndefinedVariable { source, .. }
+            | ConfigError::Unreachable { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Unreadable { reason, .. } => write!(f, "{}", reason),
+            ConfigError::DuplicateField { key, .. } => write!(f, "field {} is defined more than once", key),
+            ConfigError::DuplicateDataSource { name } => {
+                write!(f, "data source {} is defined more than once", name)
+            }
+            ConfigError::UnknownField { source, setting, key } => {
+                write!(f, "data source {}: {} unknown field {}", source, setting, key)
+            }
+            ConfigError::MistypedField { source, setting, key } => {
+                write!(f, "data source {}: {} of field {} of the wrong type", source, setting, key)
+            }
+            ConfigError::InvalidWeight { source, weight } => write!(
+                f,
+                "data source {}: weight {}, expected a finite number of at least 0",
+                source, weight
+            ),
+            ConfigError::InvalidCalibration {
+                source,
+                key,
+                sigma_inflation,
+            } => write!(
+                f,
+                "data source {}: calibration of {} with sigma_inflation {}, expected a positive factor",
+                source, key, sigma_inflation
+            ),
+            ConfigError::UnknownMockField { entry, key, .. } => {
+                write!(f, "{} reports unknown field {}", entry, key)
+            }
+            ConfigError::MistypedMockField { entry, key, .. } => {
+                write!(f, "{} reports a value of the wrong type for field {}", entry, key)
+            }
+            ConfigError::InvalidMockValue { entry, reason, .. } => {
+                write!(f, "{} reports {}", entry, reason)
+            }
+            ConfigError::UndefinedVariable { source, variable } => {
+                write!(f, "data source {}: environment variable {} is not set", source, variable)
+            }
+            ConfigError::Unreachable { source, reason } => {
+                write!(f, "data source {} is not reachable: {}", source, reason)
+            }
+            ConfigError::DependencyCycle(cycle) => write!(f, "{}", cycle),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Keys of a mapping in the order they appear, including repeated ones
+#[derive(Default)]
+struct MappingKeys(Vec<String>);
+
+impl<'de> Deserialize<'de> for MappingKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeysVisitor;
+        impl<'de> Visitor<'de> for KeysVisitor {
+            type Value = MappingKeys;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a mapping")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut keys = vec![];
+                while let Some((key, _)) = map.next_entry::<String, IgnoredAny>()? {
+                    keys.push(key);
+                }
+                Ok(MappingKeys(keys))
+            }
+        }
+        deserializer.deserialize_map(KeysVisitor)
+    }
+}
+
+/// Field keys of a config file as written, before the `config` crate keeps only the last of repeated keys
+#[derive(Deserialize)]
+struct FieldKeys {
+    #[serde(default)]
+    fields: MappingKeys,
+}
+
+/// Field keys defined more than once in a YAML or JSON file. They are read from the `fields` section of config
+/// files, and from the top level of included files
+fn duplicate_fields(path: &Path, included: bool) -> Vec<String> {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return vec![];
+    };
+    let keys: Option<MappingKeys> = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") if included => serde_yaml::from_str(&text).ok(),
+        Some("yaml" | "yml") => serde_yaml::from_str::<FieldKeys>(&text).ok().map(|k| k.fields),
+        Some("json") if included => serde_json::from_str(&text).ok(),
+        Some("json") => serde_json::from_str::<FieldKeys>(&text).ok().map(|k| k.fields),
+        _ => None,
+    };
+    let mut seen = HashSet::new();
+    let mut duplicates = vec![];
+    for key in keys.map(|k| k.0).unwrap_or_default() {
+        if !seen.insert(key.clone()) && !duplicates.contains(&key) {
+            duplicates.push(key);
+        }
+    }
+    duplicates
+}
+
+/// Replaces each `${VAR}` by the value of the environment variable `VAR`. Fails with the name of the first
+/// variable that is not set
+fn interpolate(text: &str) -> Result<String, String> {
+    let mut interpolated = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        let variable = &rest[start + 2..start + length];
+        interpolated += &rest[..start];
+        interpolated += &std::env::var(variable).map_err(|_| variable.to_string())?;
+        rest = &rest[start + length + 1..];
+    }
+    interpolated += rest;
+    Ok(interpolated)
+}
+
+impl DataSourceConfig {
+    /// Connects to the configured data source. If that fails, the data source is kept as unavailable
+    pub async fn to_configured_data_source(self) -> ConfiguredDataSource {
+        let m: Box<dyn DataSource + Sync + Send> = match self.source {
+            // DataSourceType::OpenFoodFacts => Box::new(OpenFoodFactsDataSource::default()),
+            DataSourceType::Grpc { address } => {
+                let ds = GrpcDataSource::new(address).await;
+                match ds {
+                    Ok(ds) => Box::new(ds),
+                    Err(e) => {
+                        let reason = format!("failed to connect to gRPC server: {}", e);
+                        eprintln!("Data source {}: {}", self.name, reason);
+                        Box::new(UnavailableDataSource { reason })
+                    }
+                }
+            }
+            DataSourceType::Mock { data_path } => match MockDataSource::load_from_file(&data_path) {
+                Ok(ds) => Box::new(ds),
+                Err(e) => {
+                    let reason = format!("failed to load {}: {}", data_path.display(), e);
+                    eprintln!("Data source {}: {}", self.name, reason);
+                    Box::new(UnavailableDataSource { reason })
+                }
+            },
+            DataSourceType::Rest { base_url } => Box::new(RestDatasource::new(base_url)),
+        };
+        let m: Box<dyn DataSource + Sync + Send> = match self.resilience {
+            Some(resilience) => Box::new(ResilientDataSource::new(
+                self.name.clone(),
+                m,
+                resilience,
+                self.timeout_ms.map(Duration::from_millis),
+            )),
+            None => m,
+        };
+        let m: Box<dyn DataSource + Sync + Send> = match self.cache {
+            Some(cache) => {
+                let mut cached = CachedDataSource::new(
+                    self.name.clone(),
+                    m,
+                    Duration::from_secs(cache.ttl_seconds),
+                    cache.capacity,
+                );
+                if let Some(path) = cache.persist_path {
+                    if let Err(e) = cached.persist_to(&path) {
+                        eprintln!(
+                            "Data source {}: failed to open cache at {}, caching in memory only: {}",
+                            self.name,
+                            path.display(),
+                            e
+                        );
                     }
                 }
-                DataSourceConfig::Mock { data_path } => {
-                    Box::new(MockDataSource::load_from_file(data_path))
+                Box::new(cached)
+            }
+            None => m,
+        };
+        let provides = match self.provides {
+            Some(fields) => Some(fields.into_iter().collect()),
+            None => m.provided_fields(),
+        };
+        ConfiguredDataSource {
+            name: self.name,
+            description: self.description,
+            tags: self.tags,
+            data_source: m,
+            confusion_matrices: self.confusion_matrices,
+            weight: self.weight,
+            calibration: self.calibration,
+            correlation_group: self.correlation_group,
+            timeout: self.timeout_ms.map(Duration::from_millis),
+            provides,
+            consumes: self.consumes,
+        }
+    }
+
+    /// Checks that the fields the settings refer to are configured, with the type the settings apply to,
+    /// and that the weight and the calibrations are in range
+    fn check_field_references(&self, fields: &FieldsConfig) -> Vec<ConfigError> {
+        let mut errors = vec![];
+        let references = self
+            .provides
+            .iter()
+            .flatten()
+            .map(|key| ("provides", key, None))
+            .chain(self.consumes.iter().map(|key| ("consumes", key, None)))
+            .chain(self.confusion_matrices.keys().map(|key| ("confusion matrix", key, Some(true))))
+            .chain(self.calibration.keys().map(|key| ("calibration", key, Some(false))));
+        for (setting, key, string) in references {
+            let error = match (fields.get(key).map(|f| &f.field_type), string) {
+                (None, _) => ConfigError::UnknownField {
+                    source: self.name.clone(),
+                    setting,
+                    key: key.clone(),
+                },
+                (Some(FieldType::String), Some(false)) | (Some(FieldType::Float { .. }), Some(true)) => {
+                    ConfigError::MistypedField {
+                        source: self.name.clone(),
+                        setting,
+                        key: key.clone(),
+                    }
                 }
-                DataSourceConfig::Rest { base_url } => Box::new(RestDatasource::new(base_url)),
+                _ => continue,
             };
-            new_ds.push(m);
+            errors.push(error);
         }
-        SearchEngine {
-            search_fields: self.fields,
-            data_sources: new_ds,
+        if !self.weight.is_finite() || self.weight < 0.0 {
+            errors.push(ConfigError::InvalidWeight {
+                source: self.name.clone(),
+                weight: self.weight,
+            });
         }
+        for (key, calibration) in &self.calibration {
+            if !calibration.sigma_inflation.is_finite() || calibration.sigma_inflation <= 0.0 {
+                errors.push(ConfigError::InvalidCalibration {
+                    source: self.name.clone(),
+                    key: key.clone(),
+                    sigma_inflation: calibration.sigma_inflation,
+                });
+            }
+        }
+        errors
+    }
+
+    /// Checks that a remote source answers at all. Mock sources are always reachable
+    pub async fn check_reachable(&self) -> Result<(), ConfigError> {
+        let reason = match &self.source {
+            DataSourceType::Mock { .. } => return Ok(()),
+            DataSourceType::Rest { base_url } => reqwest::Client::new()
+                .get(base_url)
+                .timeout(REACHABILITY_TIMEOUT)
+                .send()
+                .await
+                .err()
+                .map(|e| e.to_string()),
+            DataSourceType::Grpc { address } => {
+                match tokio::time::timeout(REACHABILITY_TIMEOUT, GrpcDataSource::new(address.clone())).await {
+                    Ok(Ok(_)) => None,
+                    Ok(Err(e)) => Some(e.to_string()),
+                    Err(_) => Some("timed out".to_string()),
+                }
+            }
+        };
+        match reason {
+            Some(reason) => Err(ConfigError::Unreachable {
+                source: self.name.clone(),
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks that a Mock file can be read, and that its results only report valid values of configured fields
+fn check_mock_file(data_path: &Path, fields: &FieldsConfig) -> Vec<ConfigError> {
+    let mock = match MockDataSource::load_from_file(data_path) {
+        Ok(mock) => mock,
+        Err(e) => {
+            return vec![ConfigError::Unreadable {
+                file: data_path.to_path_buf(),
+                reason: e.to_string(),
+            }]
+        }
+    };
+    let entries = mock
+        .data
+        .iter()
+        .map(|(query, result)| (MockEntry::Query(query.clone()), result))
+        .chain(mock.records.iter().map(|record| {
+            let keys = record.keys.clone().into_iter().collect();
+            (MockEntry::Record(keys), &record.result)
+        }));
+    let mut errors = vec![];
+    for (entry, result) in entries {
+        check_mock_result(data_path, entry, result, fields, &mut errors);
+    }
+    errors
+}
+
+fn check_mock_result(
+    file: &Path,
+    entry: MockEntry,
+    result: &SearchResult,
+    fields: &FieldsConfig,
+    errors: &mut Vec<ConfigError>,
+) {
+    let reported = result
+        .numeric_fields
+        .iter()
+        .map(|(key, value)| (key, false, value.validate()))
+        .chain(result.string_fields.iter().map(|(key, value)| (key, true, value.validate())));
+    for (key, string, validity) in reported {
+        let error = match (fields.get(key).map(|f| &f.field_type), validity) {
+            (None, _) => ConfigError::UnknownMockField {
+                file: file.to_path_buf(),
+                entry: entry.clone(),
+                key: key.clone(),
+            },
+            (Some(FieldType::String), _) if !string => ConfigError::MistypedMockField {
+                file: file.to_path_buf(),
+                entry: entry.clone(),
+                key: key.clone(),
+            },
+            (Some(FieldType::Float { .. }), _) if string => ConfigError::MistypedMockField {
+                file: file.to_path_buf(),
+                entry: entry.clone(),
+                key: key.clone(),
+            },
+            (_, Err(reason)) => ConfigError::InvalidMockValue {
+                file: file.to_path_buf(),
+                entry: entry.clone(),
+                reason: format!("{}: {}", key, reason),
+            },
+            (_, Ok(())) => continue,
+        };
+        errors.push(error);
+    }
+}
+
+impl Config {
+    /// Reads a config file in any format supported by the `config` crate
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, ConfigError> {
+        Self::load_layered(&[path.into()])
+    }
+
+    /// Reads config files and merges them in order: mappings such as `fields` are merged key by key, while
+    /// later files replace lists such as `data_sources`. Adds the included fields and replaces `${VAR}`
+    /// in the addresses of remote sources by the value of the environment variable `VAR`
+    pub fn load_layered(paths: &[PathBuf]) -> Result<Self, ConfigError> {
+        let builder = paths
+            .iter()
+            .fold(config::Config::builder(), |builder, path| {
+                builder.add_source(config::File::from(path.clone()))
+            });
+        let mut config: Self = builder
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .map_err(|e| ConfigError::Unreadable {
+                file: paths.last().cloned().unwrap_or_default(),
+                reason: e.to_string(),
+            })?;
+        for path in paths {
+            config.load_errors.extend(
+                duplicate_fields(path, false)
+                    .into_iter()
+                    .map(|key| ConfigError::DuplicateField { file: path.clone(), key }),
+            );
+        }
+        for path in config.include.clone() {
+            config.include_fields(&path);
+        }
+        for source in &mut config.data_sources {
+            let address = match &mut source.source {
+                DataSourceType::Grpc { address } => address,
+                DataSourceType::Rest { base_url } => base_url,
+                DataSourceType::Mock { .. } => continue,
+            };
+            match interpolate(address) {
+                Ok(interpolated) => *address = interpolated,
+                Err(variable) => config.load_errors.push(ConfigError::UndefinedVariable {
+                    source: source.name.clone(),
+                    variable,
+                }),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Adds the fields defined in an included file. Fields already defined are kept and reported
+    fn include_fields(&mut self, path: &Path) {
+        let fields: Result<FieldsConfig, config::ConfigError> = config::Config::builder()
+            .add_source(config::File::from(path))
+            .build()
+            .and_then(|c| c.try_deserialize());
+        let fields = match fields {
+            Ok(fields) => fields,
+            Err(e) => {
+                self.load_errors.push(ConfigError::Unreadable {
+                    file: path.to_path_buf(),
+                    reason: e.to_string(),
+                });
+                return;
+            }
+        };
+        let duplicates = duplicate_fields(path, true);
+        for (key, field) in fields {
+            if duplicates.contains(&key) || self.fields.contains_key(&key) {
+                self.load_errors.push(ConfigError::DuplicateField {
+                    file: path.to_path_buf(),
+                    key: key.clone(),
+                });
+            }
+            self.fields.entry(key).or_insert(field);
+        }
+    }
+
+    /// Checks the settings and the Mock files they reference, without connecting to remote sources.
+    /// Returns all problems found
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = self.load_errors.clone();
+        let mut names = HashSet::new();
+        for source in &self.data_sources {
+            if !names.insert(&source.name) {
+                errors.push(ConfigError::DuplicateDataSource {
+                    name: source.name.clone(),
+                });
+            }
+            errors.extend(source.check_field_references(&self.fields));
+            if let DataSourceType::Mock { data_path } = &source.source {
+                errors.extend(check_mock_file(data_path, &self.fields));
+            }
+        }
+        errors
+    }
+
+    /// Files the config refers to, i.e. the included fields and the data of Mock sources
+    pub fn referenced_files(&self) -> Vec<PathBuf> {
+        let data_files = self.data_sources.iter().filter_map(|source| match &source.source {
+            DataSourceType::Mock { data_path } => Some(data_path.clone()),
+            _ => None,
+        });
+        self.include.iter().cloned().chain(data_files).collect()
+    }
+
+    /// Checks that all remote sources answer. Returns the ones that do not. Sources whose address refers to
+    /// an unset environment variable are left out
+    pub async fn check_reachable(&self) -> Vec<ConfigError> {
+        let interpolated = |source: &&DataSourceConfig| {
+            !self.load_errors.iter().any(|e| {
+                matches!(e, ConfigError::UndefinedVariable { source: name, .. } if *name == source.name)
+            })
+        };
+        let checks = self
+            .data_sources
+            .iter()
+            .filter(interpolated)
+            .map(DataSourceConfig::check_reachable);
+        futures::future::join_all(checks)
+            .await
+            .into_iter()
+            .filter_map(Result::err)
+            .collect()
+    }
+
+    /// Connects to the data sources. Fails if they consume fields they may provide to each other
+    pub async fn to_search_engine(self) -> Result<SearchEngine, ConfigError> {
+        let mut new_ds = Vec::with_capacity(self.data_sources.len());
+        for data_source in self.data_sources {
+            new_ds.push(data_source.to_configured_data_source().await);
+        }
+        SearchEngine::new(
+            self.fields,
+            new_ds,
+            self.search_timeout_ms.map(Duration::from_millis),
+        )
+        .map_err(ConfigError::DependencyCycle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        let dir = std::env::temp_dir().join(format!("config_validation_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mock_path = dir.join("mock.yaml");
+        std::fs::write(
+            &mock_path,
+            r#"
+data:
+  banana:
+    numeric_fields:
+      weight:
+        Normal:
+          mu: 0.2
+          sigma: -0.1
+      size:
+        Exact: 0.2
+    string_fields: {}
+"#,
+        )
+        .unwrap();
+        let config_path = dir.join("config.yaml");
+        std::fs::write(
+            &config_path,
+            format!(
+                r#"
+fields:
+  weight:
+    description: Weight
+    field_type: String
+  weight:
+    description: Weight
+    field_type:
+      Float:
+        unit: kg
+data_sources:
+  - name: mock
+    Mock:
+      data_path: {}
+    provides: [weight, color]
+  - name: mock
+    Mock:
+      data_path: {}
+    weight: -1
+"#,
+                mock_path.display(),
+                mock_path.display()
+            ),
+        )
+        .unwrap();
+        let errors = Config::load(&config_path).unwrap().validate();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        // the errors of a Mock file are reported in no particular order
+        errors[2..4].sort();
+        errors[6..8].sort();
+        assert_eq!(
+            errors,
+            [
+                "field weight is defined more than once",
+                "data source mock: provides unknown field color",
+                "result for banana reports unknown field size",
+                "result for banana reports weight: normal distribution with mu 0.2 and sigma -0.1",
+                "data source mock is defined more than once",
+                "data source mock: weight -1, expected a finite number of at least 0",
+                "result for banana reports unknown field size",
+                "result for banana reports weight: normal distribution with mu 0.2 and sigma -0.1",
+            ]
+        );
+    }
+
+    #[test]
+    fn layered_config() {
+        let dir = std::env::temp_dir().join(format!("layered_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let include_path = dir.join("fields.yaml");
+        std::fs::write(&include_path, "color:\n  description: Color\n  field_type: String\n").unwrap();
+        let base_path = dir.join("base.yaml");
+        std::fs::write(
+            &base_path,
+            format!(
+                "fields:\n  weight:\n    description: Weight\n    field_type:\n      Float:\n        unit: kg\ninclude: [{}]\ndata_sources: []\n",
+                include_path.display()
+            ),
+        )
+        .unwrap();
+        let environment_path = dir.join("production.yaml");
+        std::fs::write(
+            &environment_path,
+            r#"
+data_sources:
+  - name: colors
+    Rest:
+      base_url: http://${LAYERED_CONFIG_TEST_HOST}:5000
+  - name: weights
+    Grpc:
+      address: ${LAYERED_CONFIG_TEST_MISSING}
+"#,
+        )
+        .unwrap();
+        std::env::set_var("LAYERED_CONFIG_TEST_HOST", "colors.internal");
+        let config = Config::load_layered(&[base_path, environment_path]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut keys: Vec<&String> = config.fields.keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["color", "weight"]);
+        assert!(matches!(
+            &config.data_sources[0].source,
+            DataSourceType::Rest { base_url } if base_url == "http://colors.internal:5000"
+        ));
+        let errors = config.validate();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            ConfigError::UndefinedVariable { source, variable }
+                if source == "weights" && variable == "LAYERED_CONFIG_TEST_MISSING"
+        ));
     }
 }
@@ -1,61 +1,193 @@
 use std::collections::{HashMap, HashSet};
 
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-use crate::{numeric::NumericFieldValue, string::StringFieldValue};
+use crate::{datasource::DataSourceError, numeric::NumericFieldValue, string::StringFieldValue};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 pub struct SearchResult{
+    /// Identifiers of the entity, keyed by scheme, e.g. `gtin` or `internal_id`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ids: HashMap<String, String>,
     /// Map from the field key to its value
     pub numeric_fields: HashMap<String, NumericFieldValue>,
     pub string_fields: HashMap<String, StringFieldValue>,
 }
-impl Default for SearchResult {
-    fn default() -> Self {
-        Self {
-            numeric_fields: HashMap::new(),
-            string_fields: HashMap::new(),
+
+/// Report of a data source, prepared for fusion with the reports of the other data sources
+#[derive(Debug, Clone)]
+pub struct SourceReport {
+    pub result: SearchResult,
+    /// Exponent applied to the likelihoods of the report
+    pub weight: f64,
+    /// Reports of sources in the same correlation group share evidence and are averaged before fusion
+    pub correlation_group: Option<String>,
+}
+
+impl SourceReport {
+    pub fn new(result: SearchResult) -> Self {
+        SourceReport {
+            result,
+            weight: 1.0,
+            correlation_group: None,
         }
     }
 }
 
-impl<'b> SearchResult {
-    pub fn merge(results: &'b Vec<Self>) -> Self {
-        let mut string_keys = HashSet::new();
-        for (key, _) in results
-            .into_iter()
-            .flat_map(|result| result.string_fields.clone())
-        {
-            string_keys.insert(key.clone());
+impl SearchResult {
+    /// Whether no field is reported
+    pub fn is_empty(&self) -> bool {
+        self.numeric_fields.is_empty() && self.string_fields.is_empty()
+    }
+
+    /// Checks that all reported values are proper distributions
+    pub fn validate(&self) -> Result<(), DataSourceError> {
+        for (key, value) in &self.numeric_fields {
+            value
+                .validate()
+                .map_err(|e| DataSourceError::InvalidValue(format!("{}: {}", key, e)))?;
         }
-        let mut numeric_keys = HashSet::new();
-        for (key, _) in results
+        for (key, value) in &self.string_fields {
+            value
+                .validate()
+                .map_err(|e| DataSourceError::InvalidValue(format!("{}: {}", key, e)))?;
+        }
+        Ok(())
+    }
+
+    pub fn merge(results: &[Self]) -> Self {
+        let reports: Vec<SourceReport> = results.iter().cloned().map(SourceReport::new).collect();
+        Self::merge_reports(&reports)
+    }
+
+    /// Values of one field reported by the sources, together with the exponent they are fused with.
+    /// Reports with a weight of zero or less are ignored. Within a correlation group, the weights are
+    /// divided by the number of group members reporting the field, averaging their likelihoods in log space.
+    fn field_values<'a, T>(
+        reports: &'a [SourceReport],
+        get: impl Fn(&'a SearchResult) -> Option<&'a T>,
+    ) -> Vec<(&'a T, f64)> {
+        let reporting: Vec<(&T, &SourceReport)> = reports
+            .iter()
+            .filter(|report| report.weight > 0.0)
+            .filter_map(|report| get(&report.result).map(|value| (value, report)))
+            .collect();
+        let mut group_sizes: HashMap<&str, usize> = HashMap::new();
+        for (_, report) in &reporting {
+            if let Some(group) = &report.correlation_group {
+                *group_sizes.entry(group).or_default() += 1;
+            }
+        }
+        reporting
             .into_iter()
-            .flat_map(|result| result.numeric_fields.clone())
-        {
-            numeric_keys.insert(key.clone());
+            .map(|(value, report)| {
+                let group_size = match &report.correlation_group {
+                    Some(group) => group_sizes[group.as_str()],
+                    None => 1,
+                };
+                (value, report.weight / group_size as f64)
+            })
+            .collect()
+    }
+
+    /// Merges the reports of several data sources, raising the likelihoods of each report to the power of its weight
+    pub fn merge_reports(reports: &[SourceReport]) -> Self {
+        let mut string_keys = HashSet::new();
+        let mut numeric_keys = HashSet::new();
+        let mut ids = HashMap::new();
+        for report in reports.iter().filter(|report| report.weight > 0.0) {
+            string_keys.extend(report.result.string_fields.keys().cloned());
+            numeric_keys.extend(report.result.numeric_fields.keys().cloned());
+            for (scheme, id) in &report.result.ids {
+                ids.entry(scheme.clone()).or_insert_with(|| id.clone());
+            }
         }
         let mut string_fields = HashMap::new();
         let mut numeric_fields = HashMap::new();
         for key in numeric_keys {
-            let values= results
+            let values = Self::field_values(reports, |result| result.numeric_fields.get(&key))
                 .into_iter()
-                .filter_map(|result| result.numeric_fields.get(&key))
-                .map(|value| value.to_owned())
+                .map(|(value, exponent)| {
+                    if exponent == 1.0 {
+                        value.to_owned()
+                    } else {
+                        value.temper(exponent)
+                    }
+                })
                 .collect();
             numeric_fields.insert(key, NumericFieldValue::merge(values));
         }
         for key in string_keys {
-            let values = results
+            let values = Self::field_values(reports, |result| result.string_fields.get(&key))
                 .into_iter()
-                .filter_map(|result| result.string_fields.get(&key))
-                .map(|value| value.to_owned())
+                .map(|(value, exponent)| {
+                    if exponent == 1.0 {
+                        value.to_owned()
+                    } else {
+                        value.temper(exponent)
+                    }
+                })
                 .collect();
             string_fields.insert(key, StringFieldValue::merge(values));
         }
         SearchResult {
+            ids,
             string_fields,
             numeric_fields,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn correlation_group() {
+        let result = SearchResult {
+            numeric_fields: HashMap::from([(
+                "weight".to_string(),
+                NumericFieldValue::Normal { sigma: 1.0, mu: 0.0 },
+            )]),
+            ..Default::default()
+        };
+        let grouped = |group: &str| SourceReport {
+            correlation_group: Some(group.to_string()),
+            ..SourceReport::new(result.clone())
+        };
+        let merged = SearchResult::merge_reports(&[grouped("a"), grouped("a")]);
+        assert!(
+            (merged.numeric_fields["weight"].sigma() - 1.0).abs() < 0.01,
+            "shared evidence is counted twice"
+        );
+        let merged = SearchResult::merge_reports(&[grouped("a"), grouped("b")]);
+        assert!(
+            (merged.numeric_fields["weight"].sigma() - 0.5_f64.sqrt()).abs() < 0.01,
+            "independent evidence is not combined"
+        );
+
+        let reporting = |value: NumericFieldValue| SourceReport {
+            correlation_group: Some("a".to_string()),
+            ..SourceReport::new(SearchResult {
+                numeric_fields: HashMap::from([("weight".to_string(), value)]),
+                ..Default::default()
+            })
+        };
+        let exact = reporting(NumericFieldValue::Exact(100.0));
+        let merged = SearchResult::merge_reports(&[exact.clone(), exact]);
+        assert!(
+            matches!(merged.numeric_fields["weight"], NumericFieldValue::Exact(v) if v == 100.0),
+            "agreeing exact reports of a group do not fuse to the same value"
+        );
+        let uniform = reporting(NumericFieldValue::Uniform { min: 1.0, max: 3.0 });
+        let merged = SearchResult::merge_reports(&[uniform.clone(), uniform]);
+        let weight = &merged.numeric_fields["weight"];
+        assert!((weight.mu() - 2.0).abs() < 0.01, "has wrong mu");
+        assert!(
+            (weight.sigma() - 2.0 / 12.0_f64.sqrt()).abs() < 0.01,
+            "agreeing uniform reports of a group do not fuse to the same value"
+        );
+        assert!((weight.get_value(1.5) - 0.5).abs() < 0.01 && weight.get_value(3.5) == 0.0);
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -11,20 +11,139 @@ pub enum StringFieldValue {
     Error,
 }
 
+/// Confusion matrix `P(reported | true)` of a data source for one string field.
+/// Maps each true value to the distribution of values the source reports for it.
+/// True values without a row are assumed to always be reported correctly.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ConfusionMatrix(pub HashMap<String, HashMap<String, f64>>);
+
+impl ConfusionMatrix {
+    /// Probability that the source reports `reported` if the true value is `actual`
+    pub fn probability(&self, actual: &str, reported: &str) -> f64 {
+        match self.0.get(actual) {
+            Some(row) => *row.get(reported).unwrap_or(&0.0),
+            None => {
+                if actual == reported {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
 impl StringFieldValue {
+    /// Converts a reported value into a likelihood over the true values, using the confusion matrix of the reporting source.
+    /// The likelihood of a true value `t` is `sum_r P(reported = r | t) * q(r)`, where `q` is the reported distribution.
+    pub fn to_likelihood(&self, confusion_matrix: &ConfusionMatrix) -> Self {
+        let reported = match self.probabilities() {
+            Some(p) => p,
+            None => return StringFieldValue::Error,
+        };
+        let candidates: HashSet<&String> =
+            confusion_matrix.0.keys().chain(reported.keys()).collect();
+        let mut result = HashMap::new();
+        let mut likelihood_sum = 0.0;
+        for actual in candidates {
+            let likelihood: f64 = reported
+                .iter()
+                .map(|(r, q)| confusion_matrix.probability(actual, r) * q)
+                .sum();
+            if likelihood > 0.0 {
+                likelihood_sum += likelihood;
+                result.insert(actual.clone(), likelihood);
+            }
+        }
+        if likelihood_sum == 0.0 {
+            return StringFieldValue::Error;
+        }
+        for (_, value) in result.iter_mut() {
+            *value /= likelihood_sum;
+        }
+        StringFieldValue::Distribution(result)
+    }
+
+    /// Raises the probabilities to the power of `weight` and renormalizes them.
+    /// Weights below 1 flatten the distribution, weights above 1 sharpen it.
+    /// An exact value is tempered as the distribution giving it probability 1
+    pub fn temper(&self, weight: f64) -> Self {
+        let probabilities = match self.probabilities() {
+            Some(probabilities) => probabilities,
+            None => return StringFieldValue::Error,
+        };
+        let tempered: HashMap<String, f64> = probabilities
+            .into_iter()
+            .map(|(k, p)| (k, p.powf(weight)))
+            .collect();
+        let probability_sum: f64 = tempered.values().sum();
+        if probability_sum == 0.0 {
+            return StringFieldValue::Error;
+        }
+        StringFieldValue::Distribution(
+            tempered
+                .into_iter()
+                .map(|(k, p)| (k, p / probability_sum))
+                .collect(),
+        )
+    }
+
+    fn probabilities(&self) -> Option<HashMap<String, f64>> {
+        match self {
+            StringFieldValue::Exact(e) => Some(HashMap::from([(e.clone(), 1.0)])),
+            StringFieldValue::Distribution(d) => Some(d.clone()),
+            StringFieldValue::Error => None,
+        }
+    }
+
+    /// Checks that the probabilities are between 0 and 1 and sum to at most 1. Returns the reason if they do not
+    pub fn validate(&self) -> Result<(), String> {
+        if let StringFieldValue::Distribution(d) = self {
+            if let Some((value, p)) = d.iter().find(|(_, p)| !(0.0..=1.0).contains(*p)) {
+                return Err(format!("probability {} of {}", p, value));
+            }
+            let total: f64 = d.values().sum();
+            if total > 1.0 + 1e-9 {
+                return Err(format!("probabilities summing to {}", total));
+            }
+        }
+        Ok(())
+    }
+
+    /// Most probable value and its probability. Missing for errors and empty distributions
+    pub fn most_probable(&self) -> Option<(&String, f64)> {
+        match self {
+            StringFieldValue::Exact(value) => Some((value, 1.0)),
+            StringFieldValue::Distribution(d) => d
+                .iter()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(value, p)| (value, *p)),
+            StringFieldValue::Error => None,
+        }
+    }
+
+    /// Total variation distance to another distribution, between 0 (identical) and 1 (disjoint).
+    /// Missing if either is an error
+    pub fn total_variation_distance(&self, other: &Self) -> Option<f64> {
+        let (p, q) = (self.probabilities()?, other.probabilities()?);
+        let keys: HashSet<&String> = p.keys().chain(q.keys()).collect();
+        let distance = keys
+            .into_iter()
+            .map(|k| (p.get(k).unwrap_or(&0.0) - q.get(k).unwrap_or(&0.0)).abs())
+            .sum::<f64>();
+        Some(distance / 2.0)
+    }
+
     pub fn merge(v: Vec<Self>) -> Self {
         //panic!("Not implemented");
-        if v.len() == 0 {
+        if v.is_empty() {
             return StringFieldValue::Error;
         }
         if v.len() == 1 {
             return v[0].clone();
         }
         // propagate errors
-        if v.iter().any(|val| match val {
-            StringFieldValue::Error => true,
-            _ => false,
-        }) {
+        if v.iter().any(|val| matches!(val, StringFieldValue::Error)) {
             return StringFieldValue::Error;
         }
         let mut exacts = v.iter().filter_map(|val| match val {
@@ -49,7 +168,7 @@ impl StringFieldValue {
             None => return StringFieldValue::Error,
         };
         let mut probability_sum = 0.0;
-        for (key, _) in first_distribution {
+        for key in first_distribution.keys() {
             let probability = distributions
                 .clone()
                 .map(|d| d.get(key).unwrap_or(&0.0))
@@ -68,3 +187,53 @@ impl StringFieldValue {
         StringFieldValue::Distribution(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confusion_matrix() {
+        let matrix = ConfusionMatrix(HashMap::from([
+            (
+                "green".to_string(),
+                HashMap::from([("green".to_string(), 0.6), ("yellow".to_string(), 0.4)]),
+            ),
+            (
+                "yellow".to_string(),
+                HashMap::from([("yellow".to_string(), 0.9), ("green".to_string(), 0.1)]),
+            ),
+        ]));
+        let likelihood = match StringFieldValue::Exact("yellow".into()).to_likelihood(&matrix) {
+            StringFieldValue::Distribution(d) => d,
+            _ => panic!("expected a distribution"),
+        };
+        assert!((likelihood["yellow"] - 0.9 / 1.3).abs() < 1e-9);
+        assert!((likelihood["green"] - 0.4 / 1.3).abs() < 1e-9);
+        let unknown = match StringFieldValue::Exact("red".into()).to_likelihood(&matrix) {
+            StringFieldValue::Distribution(d) => d,
+            _ => panic!("expected a distribution"),
+        };
+        assert_eq!(unknown.len(), 1, "values outside the matrix are reported correctly");
+        assert!((unknown["red"] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tempered() {
+        let flattened = match StringFieldValue::Distribution(HashMap::from([
+            ("yellow".to_string(), 0.8),
+            ("green".to_string(), 0.2),
+        ]))
+        .temper(0.5)
+        {
+            StringFieldValue::Distribution(d) => d,
+            _ => panic!("expected a distribution"),
+        };
+        assert!((flattened["yellow"] - 2.0 / 3.0).abs() < 1e-9, "not flattened");
+        let exact = match StringFieldValue::Exact("yellow".into()).temper(0.5) {
+            StringFieldValue::Distribution(d) => d,
+            _ => panic!("exact value not tempered as a distribution"),
+        };
+        assert_eq!(exact, HashMap::from([("yellow".to_string(), 1.0)]));
+    }
+}
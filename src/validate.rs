@@ -0,0 +1,148 @@
+use std::path::{Path, PathBuf};
+
+use crate::search_engine_config::{Config, ConfigError, MockEntry};
+
+/// Problem found in a config or Mock file
+#[derive(Debug)]
+pub struct Problem {
+    pub file: PathBuf,
+    /// Line the problem was found at, counting from 1, if known
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+/// Text of a file, used to point problems to the line they stem from
+struct SourceFile {
+    lines: Vec<String>,
+}
+
+impl SourceFile {
+    fn read(path: &Path) -> Self {
+        SourceFile {
+            lines: std::fs::read_to_string(path)
+                .map(|text| text.lines().map(str::to_string).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// First line matching the predicate, counting from 1
+    fn find(&self, predicate: impl Fn(&str) -> bool) -> Option<usize> {
+        self.lines.iter().position(|line| predicate(line)).map(|i| i + 1)
+    }
+
+    /// Last line matching the predicate, counting from 1
+    fn find_last(&self, predicate: impl Fn(&str) -> bool) -> Option<usize> {
+        self.lines.iter().rposition(|line| predicate(line)).map(|i| i + 1)
+    }
+
+    /// Line of the mapping key `key`, quoted or not
+    fn find_key(&self, key: &str) -> Option<usize> {
+        self.find(|line| is_key(line, key))
+    }
+
+    /// Line of the last mapping key `key` indented like the first one, skipping nested keys of the same name
+    fn find_repeated_key(&self, key: &str) -> Option<usize> {
+        let first = &self.lines[self.find_key(key)? - 1];
+        let indent = first.len() - first.trim_start().len();
+        self.find_last(|line| is_key(line, key) && line.len() - line.trim_start().len() == indent)
+    }
+
+    /// Line of the data source named `name`
+    fn find_data_source(&self, name: &str) -> Option<usize> {
+        self.find(|line| is_data_source(line, name))
+    }
+
+    /// Line of the query key or of one of the record keys of a Mock entry
+    fn find_entry(&self, entry: &MockEntry) -> Option<usize> {
+        match entry {
+            MockEntry::Query(query) => self.find_key(query),
+            MockEntry::Record(keys) => keys.iter().find_map(|(k, v)| {
+                self.find(|line| line.contains(&format!("{}:", k)) && line.contains(v.as_str()))
+            }),
+        }
+    }
+}
+
+/// Whether the line starts the mapping key `key`, quoted or not
+fn is_key(line: &str, key: &str) -> bool {
+    let line = line.trim();
+    [format!("{}:", key), format!("\"{}\":", key), format!("'{}':", key)]
+        .iter()
+        .any(|k| line.starts_with(k.as_str()) || line.starts_with(&format!("- {}", k)))
+}
+
+/// Whether the line holds the name of the data source `name`
+fn is_data_source(line: &str, name: &str) -> bool {
+    line.trim_start_matches(['-', ' ']).trim_end() == format!("name: {}", name)
+}
+
+/// Points each error to the file and line it stems from. Data sources are looked up in the config files
+/// from the last to the first, as later files replace the sources of earlier ones
+pub fn locate(config_paths: &[PathBuf], errors: Vec<ConfigError>) -> Vec<Problem> {
+    let config_files: Vec<(&PathBuf, SourceFile)> =
+        config_paths.iter().rev().map(|path| (path, SourceFile::read(path))).collect();
+    let last_config = config_paths.last().cloned().unwrap_or_default();
+    let find_in_config = |find: &dyn Fn(&SourceFile) -> Option<usize>| {
+        config_files
+            .iter()
+            .find_map(|(path, file)| find(file).map(|line| ((*path).clone(), Some(line))))
+            .unwrap_or((last_config.clone(), None))
+    };
+    errors
+        .into_iter()
+        .map(|error| {
+            let (file, line) = match &error {
+                ConfigError::Unreadable { file, .. } => (file.clone(), None),
+                ConfigError::UnknownMockField { file, entry, .. }
+                | ConfigError::MistypedMockField { file, entry, .. }
+                | ConfigError::InvalidMockValue { file, entry, .. } => {
+                    (file.clone(), SourceFile::read(file).find_entry(entry))
+                }
+                // point to the repeated definition rather than the first one
+                ConfigError::DuplicateField { file, key } => {
+                    (file.clone(), SourceFile::read(file).find_repeated_key(key))
+                }
+                ConfigError::DuplicateDataSource { name } => {
+                    find_in_config(&|file| file.find_last(|line| is_data_source(line, name)))
+                }
+                _ => match error.data_source() {
+                    Some(name) => find_in_config(&|file| file.find_data_source(name)),
+                    None => (last_config.clone(), None),
+                },
+            };
+            Problem {
+                file,
+                line,
+                message: error.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Checks the config files and the files they reference, and connects to the data sources.
+/// Returns all problems found
+pub async fn validate(config_paths: &[PathBuf]) -> Vec<Problem> {
+    let config = match Config::load_layered(config_paths) {
+        Ok(config) => config,
+        Err(e) => return locate(config_paths, vec![e]),
+    };
+    let mut errors = config.validate();
+    let connectable = errors.is_empty();
+    errors.extend(config.check_reachable().await);
+    // dependencies are only known once the sources announced the fields they provide
+    if connectable {
+        if let Err(e) = config.to_search_engine().await {
+            errors.push(e);
+        }
+    }
+    locate(config_paths, errors)
+}